@@ -0,0 +1,401 @@
+use chrono::naive::datetime::{NaiveDateTime};
+use std::collections::{BTreeMap};
+use std::hash::{Hash, Hasher};
+
+use {Clock, Order, Orderbook, SystemClock};
+
+
+/// All resting orders at one price, aggregated.
+#[derive(Copy, Clone, Debug)]
+pub struct Level {
+    pub price: u64,
+    pub qty: u64,
+    pub order_count: u64,
+}
+
+/// A normalized level-2 book: each side price-aggregated and sorted best-first.
+#[derive(Clone, Debug)]
+pub struct L2Book {
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+    pub timestamp: NaiveDateTime,
+}
+
+/// A compact summary of a book, cheap to copy into a log line or metrics sample.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MarketSnapshot {
+    pub best_bid: Option<u64>,
+    pub best_bid_qty: u64,
+    pub best_ask: Option<u64>,
+    pub best_ask_qty: u64,
+    pub spread: Option<u64>,
+    /// Total shares resting on each side.
+    pub bid_depth: u64,
+    pub ask_depth: u64,
+    pub timestamp: NaiveDateTime,
+}
+
+/// How many levels per side `Orderbook::checksum` covers.
+pub const CHECKSUM_DEPTH: usize = 10;
+
+
+impl Orderbook {
+    pub fn builder() -> OrderbookBuilder {
+        OrderbookBuilder::new()
+    }
+
+    pub fn to_l2(&self) -> L2Book {
+        L2Book {
+            bids: aggregate_levels(&self.bids, true),
+            asks: aggregate_levels(&self.asks, false),
+            timestamp: self.timestamp,
+        }
+    }
+
+    /// The highest-priced bid level.
+    pub fn best_bid(&self) -> Option<Level> {
+        aggregate_levels(&self.bids, true).into_iter().next()
+    }
+
+    /// The lowest-priced ask level.
+    pub fn best_ask(&self) -> Option<Level> {
+        aggregate_levels(&self.asks, false).into_iter().next()
+    }
+
+    /// Best ask minus best bid, or `None` for a one-sided book. A crossed book
+    /// reports zero.
+    pub fn spread(&self) -> Option<u64> {
+        match (self.best_bid(), self.best_ask()) {
+            (Some(bid), Some(ask)) => Some(ask.price.saturating_sub(bid.price)),
+            _ => None,
+        }
+    }
+
+    /// The best `n` aggregated levels on each side as `(bids, asks)`, or fewer if
+    /// the book isn't that deep.
+    pub fn top(&self, n: usize) -> (Vec<Level>, Vec<Level>) {
+        let mut bids = aggregate_levels(&self.bids, true);
+        let mut asks = aggregate_levels(&self.asks, false);
+        bids.truncate(n);
+        asks.truncate(n);
+        (bids, asks)
+    }
+
+    /// How many shares a buy (`is_buy`) or sell would have to take to move the
+    /// best opposing price to `target_price`: everything on the asks priced below
+    /// it, or on the bids priced above it. Zero if the market is already there.
+    pub fn qty_to_move(&self, is_buy: bool, target_price: u64) -> u64 {
+        let levels = aggregate_levels(if is_buy { &self.asks } else { &self.bids }, !is_buy);
+        levels.iter()
+            .take_while(|level| if is_buy { level.price < target_price } else { level.price > target_price })
+            .map(|level| level.qty)
+            .sum()
+    }
+
+    /// A cheap fingerprint of the book's top `CHECKSUM_DEPTH` levels per side, for
+    /// checking a locally maintained book against a fresh snapshot. It only
+    /// looks at aggregated price/qty, so it ignores the timestamp and how orders
+    /// are split within a level, and it is stable across runs and platforms
+    /// (64-bit FNV-1a).
+    pub fn checksum(&self) -> u64 {
+        let (bids, asks) = self.top(CHECKSUM_DEPTH);
+
+        let mut hash = FNV_OFFSET_BASIS;
+        for side in &[bids, asks] {
+            for level in side {
+                hash = fnv1a(hash, level.price);
+                hash = fnv1a(hash, level.qty);
+            }
+            // Keep a level from being able to "move" between sides unnoticed.
+            hash = fnv1a(hash, side.len() as u64);
+        }
+        hash
+    }
+
+    /// The midpoint of the average prices paid to take `depth_qty` shares from
+    /// each side. Smoother than the top-of-book mid when the best levels are thin.
+    /// `None` if either side has fewer than `depth_qty` shares, or `depth_qty` is zero.
+    pub fn weighted_mid(&self, depth_qty: u64) -> Option<f64> {
+        let bid = walk_vwap(&aggregate_levels(&self.bids, true), depth_qty);
+        let ask = walk_vwap(&aggregate_levels(&self.asks, false), depth_qty);
+
+        match (bid, ask) {
+            (Some(bid), Some(ask)) => Some((bid + ask) / 2.0),
+            _ => None,
+        }
+    }
+
+    /// The book as seen by everyone else: `my_orders` (e.g. my open orders from the
+    /// venue) are taken out of the matching price level on the matching side.
+    ///
+    /// The public book doesn't say whose orders are whose, so an entry whose size
+    /// exactly matches one of mine is assumed to be it; failing that, my quantity
+    /// is taken from the back of the level, where the newest orders queue.
+    pub fn clamp_to_account(&self, my_orders: &[Order]) -> Orderbook {
+        let mut bids = self.bids.clone();
+        let mut asks = self.asks.clone();
+
+        for mine in my_orders {
+            let side = if mine.is_buy { &mut bids } else { &mut asks };
+            remove_own_order(side, mine);
+        }
+
+        Orderbook {
+            bids,
+            asks,
+            timestamp: self.timestamp,
+        }
+    }
+
+    /// Whether both books hold the same orders, whenever each was taken. Useful
+    /// for skipping snapshots in which nothing changed but the timestamp.
+    pub fn same_book(&self, other: &Orderbook) -> bool {
+        sorted_side(&self.bids) == sorted_side(&other.bids)
+            && sorted_side(&self.asks) == sorted_side(&other.asks)
+    }
+}
+
+
+/// Assembles an `Orderbook` by hand, e.g. for test fixtures, as in
+/// `Orderbook::builder().bid(5100, 20).ask(5200, 10).build()`.
+///
+/// Orders are kept in the sequence given. The timestamp defaults to the time
+/// `build()` is called, or to a given clock's time with `build_with_clock`.
+#[derive(Clone, Debug, Default)]
+pub struct OrderbookBuilder {
+    bids: Vec<Order>,
+    asks: Vec<Order>,
+    timestamp: Option<NaiveDateTime>,
+}
+
+impl OrderbookBuilder {
+    pub fn new() -> OrderbookBuilder {
+        OrderbookBuilder::default()
+    }
+
+    pub fn bid(mut self, price: u64, qty: u64) -> OrderbookBuilder {
+        self.bids.push(Order { price, qty, is_buy: true });
+        self
+    }
+
+    pub fn ask(mut self, price: u64, qty: u64) -> OrderbookBuilder {
+        self.asks.push(Order { price, qty, is_buy: false });
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: NaiveDateTime) -> OrderbookBuilder {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn build(self) -> Orderbook {
+        self.build_with_clock(&SystemClock)
+    }
+
+    /// Like `build`, but a book without an explicit timestamp is stamped with
+    /// `clock`'s current time.
+    pub fn build_with_clock<C: Clock>(self, clock: &C) -> Orderbook {
+        Orderbook {
+            bids: self.bids,
+            asks: self.asks,
+            timestamp: self.timestamp.unwrap_or_else(|| clock.now()),
+        }
+    }
+}
+
+
+// Two books are equal when they were taken at the same time and hold the same
+// orders on each side, in whatever sequence the venue happened to list them.
+impl PartialEq for Orderbook {
+    fn eq(&self, other: &Orderbook) -> bool {
+        self.timestamp == other.timestamp && self.same_book(other)
+    }
+}
+
+impl Eq for Orderbook {}
+
+impl Hash for Orderbook {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.timestamp.hash(state);
+        sorted_side(&self.bids).hash(state);
+        sorted_side(&self.asks).hash(state);
+    }
+}
+
+impl<'a> From<&'a Orderbook> for MarketSnapshot {
+    fn from(book: &'a Orderbook) -> MarketSnapshot {
+        let best_bid = book.best_bid();
+        let best_ask = book.best_ask();
+
+        MarketSnapshot {
+            best_bid: best_bid.map(|level| level.price),
+            best_bid_qty: best_bid.map_or(0, |level| level.qty),
+            best_ask: best_ask.map(|level| level.price),
+            best_ask_qty: best_ask.map_or(0, |level| level.qty),
+            spread: book.spread(),
+            bid_depth: book.bids.iter().map(|order| order.qty).sum(),
+            ask_depth: book.asks.iter().map(|order| order.qty).sum(),
+            timestamp: book.timestamp,
+        }
+    }
+}
+
+
+fn sorted_side(orders: &[Order]) -> Vec<(u64, u64, bool)> {
+    let mut side: Vec<_> = orders.iter().map(|order| (order.price, order.qty, order.is_buy)).collect();
+    side.sort();
+    side
+}
+
+fn aggregate_levels(orders: &[Order], is_buy: bool) -> Vec<Level> {
+    let mut by_price = BTreeMap::new();
+    for order in orders {
+        let level = by_price.entry(order.price).or_insert(Level { price: order.price, qty: 0, order_count: 0 });
+        level.qty += order.qty;
+        level.order_count += 1;
+    }
+
+    // Bids are best when highest, asks when lowest.
+    if is_buy {
+        by_price.into_values().rev().collect()
+    } else {
+        by_price.into_values().collect()
+    }
+}
+
+// Average price of the first `qty` shares on a best-first side,
+// or `None` if the side can't supply that many.
+fn walk_vwap(levels: &[Level], qty: u64) -> Option<f64> {
+    if qty == 0 {
+        return None;
+    }
+
+    let mut remaining = qty;
+    let mut cost = 0.0;
+    for level in levels {
+        let take = if level.qty < remaining { level.qty } else { remaining };
+        cost += take as f64 * level.price as f64;
+        remaining -= take;
+
+        if remaining == 0 {
+            return Some(cost / qty as f64);
+        }
+    }
+
+    None
+}
+
+fn remove_own_order(side: &mut Vec<Order>, mine: &Order) {
+    if let Some(i) = side.iter().position(|order| order.price == mine.price && order.qty == mine.qty) {
+        side.remove(i);
+        return;
+    }
+
+    let mut remaining = mine.qty;
+    for order in side.iter_mut().rev().filter(|order| order.price == mine.price) {
+        let take = if order.qty < remaining { order.qty } else { remaining };
+        order.qty -= take;
+        remaining -= take;
+
+        if remaining == 0 {
+            break;
+        }
+    }
+
+    side.retain(|order| order.qty > 0);
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn fnv1a(mut hash: u64, value: u64) -> u64 {
+    for i in 0..8 {
+        hash ^= (value >> (8 * i)) & 0xff;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::naive::datetime::{NaiveDateTime};
+    use std::collections::hash_map::{DefaultHasher};
+    use std::hash::{Hash, Hasher};
+
+    use {Orderbook};
+
+    fn hash_of(book: &Orderbook) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        book.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn top_of_book_mid(book: &Orderbook) -> f64 {
+        (book.best_bid().unwrap().price + book.best_ask().unwrap().price) as f64 / 2.0
+    }
+
+    #[test]
+    fn weighted_mid_looks_past_a_thin_best_level() {
+        // A single share at 5190 pulls the top-of-book mid up; 100 shares deep,
+        // the bid side is really around 5100.
+        let book = Orderbook::builder()
+            .bid(5190, 1)
+            .bid(5100, 99)
+            .ask(5200, 50)
+            .ask(5210, 50)
+            .build();
+
+        assert_eq!(top_of_book_mid(&book), 5195.0);
+        let mid = book.weighted_mid(100).unwrap();
+        assert!((mid - (5100.9 + 5205.0) / 2.0).abs() < 1e-9, "got {}", mid);
+        assert!(mid < top_of_book_mid(&book));
+    }
+
+    #[test]
+    fn weighted_mid_matches_the_plain_mid_within_the_best_level() {
+        let book = Orderbook::builder().bid(5100, 20).ask(5200, 10).build();
+        assert_eq!(book.weighted_mid(10), Some(top_of_book_mid(&book)));
+    }
+
+    #[test]
+    fn weighted_mid_needs_depth_qty_on_both_sides() {
+        let book = Orderbook::builder().bid(5100, 20).ask(5200, 5).ask(5300, 4).build();
+        assert_eq!(book.weighted_mid(10), None);
+        assert_eq!(Orderbook::builder().bid(5100, 20).build().weighted_mid(10), None);
+    }
+
+    #[test]
+    fn weighted_mid_of_zero_shares_is_none() {
+        let book = Orderbook::builder().bid(5100, 20).ask(5200, 10).build();
+        assert_eq!(book.weighted_mid(0), None);
+    }
+
+    #[test]
+    fn eq_and_hash_ignore_the_order_within_a_side() {
+        let at = NaiveDateTime::from_timestamp(1449219736, 680986205);
+        let book = Orderbook::builder().bid(5100, 20).bid(5050, 3).ask(5200, 10).ask(5300, 1).timestamp(at).build();
+        let shuffled = Orderbook::builder().bid(5050, 3).bid(5100, 20).ask(5300, 1).ask(5200, 10).timestamp(at).build();
+
+        assert_eq!(book, shuffled);
+        assert_eq!(hash_of(&book), hash_of(&shuffled));
+    }
+
+    #[test]
+    fn books_taken_at_different_times_differ() {
+        let earlier = Orderbook::builder().bid(5100, 20).timestamp(NaiveDateTime::from_timestamp(1449219736, 0)).build();
+        let later = Orderbook::builder().bid(5100, 20).timestamp(NaiveDateTime::from_timestamp(1449219736, 1)).build();
+
+        assert!(earlier != later);
+        assert!(earlier.same_book(&later));
+    }
+
+    #[test]
+    fn a_moved_order_is_a_different_book() {
+        let at = NaiveDateTime::from_timestamp(1449219736, 0);
+        let book = Orderbook::builder().bid(5100, 20).ask(5200, 10).timestamp(at).build();
+        let moved = Orderbook::builder().bid(5200, 10).ask(5100, 20).timestamp(at).build();
+
+        assert!(book != moved);
+    }
+}
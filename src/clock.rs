@@ -0,0 +1,112 @@
+use chrono::{UTC, Duration};
+use chrono::naive::datetime::{NaiveDateTime};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+
+/// A source of the current (UTC) time, so time-dependent helpers can be driven
+/// deterministically in tests.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> NaiveDateTime;
+    fn sleep(&self, duration: Duration);
+}
+
+// So a clock can be shared, or stored type-erased as `Arc<dyn Clock>`.
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> NaiveDateTime {
+        (**self).now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration)
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        UTC::now().naive_utc()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        if let Ok(duration) = duration.to_std() {
+            thread::sleep(duration);
+        }
+    }
+}
+
+
+/// A clock that only moves when told to. Clones share the same time, so a test
+/// can hand one clone to the code under test and advance another.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    now: Arc<Mutex<NaiveDateTime>>,
+}
+
+impl MockClock {
+    pub fn new(start: NaiveDateTime) -> MockClock {
+        MockClock { now: Arc::new(Mutex::new(start)) }
+    }
+
+    pub fn set(&self, time: NaiveDateTime) {
+        *self.now.lock().unwrap() = time;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now = *now + duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> NaiveDateTime {
+        *self.now.lock().unwrap()
+    }
+
+    // Sleeping on a mock clock just fast-forwards it.
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration};
+    use chrono::naive::datetime::{NaiveDateTime};
+
+    use super::*;
+
+    #[test]
+    fn a_mock_clock_moves_only_when_told() {
+        let start = NaiveDateTime::from_timestamp(1449219736, 0);
+        let clock = MockClock::new(start);
+        assert_eq!(clock.now(), start);
+
+        clock.advance(Duration::milliseconds(1500));
+        assert_eq!(clock.now(), start + Duration::milliseconds(1500));
+
+        clock.sleep(Duration::seconds(2));
+        assert_eq!(clock.now(), start + Duration::milliseconds(3500));
+
+        let later = NaiveDateTime::from_timestamp(1449300000, 0);
+        clock.set(later);
+        assert_eq!(clock.now(), later);
+    }
+
+    #[test]
+    fn clones_of_a_mock_clock_share_one_time() {
+        let start = NaiveDateTime::from_timestamp(1449219736, 0);
+        let clock = MockClock::new(start);
+        let shared = Arc::new(clock.clone());
+
+        clock.advance(Duration::seconds(5));
+        assert_eq!(shared.now(), start + Duration::seconds(5));
+
+        shared.set(start);
+        assert_eq!(clock.now(), start);
+    }
+}
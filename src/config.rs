@@ -0,0 +1,214 @@
+//! Loading client settings from a `stockfighter.toml`:
+//!
+//! ```toml
+//! api_key = "..."
+//! base_url = "https://api.stockfighter.io/ob/api"
+//!
+//! # Optional; read by `StrategyConfig`.
+//! [strategy]
+//! target_spread = 50
+//! order_size = 100
+//! max_position = 1000
+//! symbols = ["FOOBAR"]
+//! ```
+
+use std::fs::{File};
+use std::io::{Read};
+use std::path::{Path};
+use toml::{self, Parser, Table};
+
+use {StockfighterError, StockfighterHttpApi, StockfighterResult};
+
+
+impl StockfighterHttpApi {
+    pub fn from_config_file<P: AsRef<Path>>(path: P) -> StockfighterResult<StockfighterHttpApi> {
+        let table = read_config_table(path.as_ref())?;
+
+        let base_url = required_string(&table, "base_url")?;
+        let api_key = required_string(&table, "api_key")?;
+
+        Ok(StockfighterHttpApi::new(base_url, api_key))
+    }
+}
+
+
+/// Knobs most bots end up needing, read from the `[strategy]` table of the same
+/// config file. The crate only parses them; what they mean is up to the strategy.
+#[derive(Clone, Debug, Default)]
+pub struct StrategyConfig {
+    pub target_spread: Option<u64>,
+    pub order_size: Option<u64>,
+    pub max_position: Option<u64>,
+    pub symbols: Vec<String>,
+}
+
+impl StrategyConfig {
+    /// Reads the `[strategy]` table. A file without one yields the defaults.
+    pub fn from_config_file<P: AsRef<Path>>(path: P) -> StockfighterResult<StrategyConfig> {
+        let table = read_config_table(path.as_ref())?;
+
+        let strategy = match table.get("strategy") {
+            Some(toml::Value::Table(strategy)) => strategy,
+            Some(_) => return Err(StockfighterError::Config("Config key 'strategy' must be a table".to_owned())),
+            None => return Ok(StrategyConfig::default()),
+        };
+
+        Ok(StrategyConfig {
+            target_spread: optional_u64(strategy, "target_spread")?,
+            order_size: optional_u64(strategy, "order_size")?,
+            max_position: optional_u64(strategy, "max_position")?,
+            symbols: string_list(strategy, "symbols")?,
+        })
+    }
+}
+
+
+fn read_config_table(path: &Path) -> StockfighterResult<Table> {
+    let mut contents = String::new();
+    let read = File::open(path).and_then(|mut file| file.read_to_string(&mut contents));
+    if let Err(err) = read {
+        return Err(StockfighterError::Config(format!("Could not read config file '{}': {}", path.display(), err)));
+    }
+
+    let mut parser = Parser::new(&contents);
+    match parser.parse() {
+        Some(table) => Ok(table),
+        None => {
+            let messages: Vec<String> = parser.errors.iter().map(|err| {
+                let (line, col) = parser.to_linecol(err.lo);
+                format!("{}:{}: {}", line + 1, col + 1, err.desc)
+            }).collect();
+
+            Err(StockfighterError::Config(format!("Malformed config file '{}': {}", path.display(), messages.join("; "))))
+        },
+    }
+}
+
+fn required_string(table: &Table, key: &str) -> StockfighterResult<String> {
+    match table.get(key) {
+        Some(toml::Value::String(value)) => Ok(value.clone()),
+        Some(_) => Err(StockfighterError::Config(format!("Config key '{}' must be a string", key))),
+        None => Err(StockfighterError::Config(format!("Config is missing required key '{}'", key))),
+    }
+}
+
+fn optional_u64(table: &Table, key: &str) -> StockfighterResult<Option<u64>> {
+    match table.get(key) {
+        Some(&toml::Value::Integer(value)) if value >= 0 => Ok(Some(value as u64)),
+        Some(_) => Err(StockfighterError::Config(format!("Config key '{}' must be a non-negative integer", key))),
+        None => Ok(None),
+    }
+}
+
+fn string_list(table: &Table, key: &str) -> StockfighterResult<Vec<String>> {
+    let values = match table.get(key) {
+        Some(toml::Value::Array(values)) => values,
+        Some(_) => return Err(StockfighterError::Config(format!("Config key '{}' must be an array of strings", key))),
+        None => return Ok(Vec::new()),
+    };
+
+    values.iter().map(|value| {
+        match *value {
+            toml::Value::String(ref value) => Ok(value.clone()),
+            _ => Err(StockfighterError::Config(format!("Config key '{}' must be an array of strings", key))),
+        }
+    }).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Write};
+    use std::path::{PathBuf};
+    use std::process;
+
+    use super::*;
+
+    // Writes `contents` to a file of its own, removed again when dropped.
+    struct ConfigFile(PathBuf);
+
+    impl ConfigFile {
+        fn new(name: &str, contents: &str) -> ConfigFile {
+            let path = env::temp_dir().join(format!("stockfighter-{}-{}.toml", process::id(), name));
+            File::create(&path).unwrap().write_all(contents.as_bytes()).unwrap();
+            ConfigFile(path)
+        }
+    }
+
+    impl Drop for ConfigFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn config_error<T: ::std::fmt::Debug>(result: StockfighterResult<T>) -> String {
+        match result {
+            Err(StockfighterError::Config(message)) => message,
+            other => panic!("expected a Config error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn loads_the_client_and_strategy_settings() {
+        let file = ConfigFile::new("complete", r#"
+            api_key = "secret"
+            base_url = "https://api.stockfighter.io/ob/api"
+
+            [strategy]
+            order_size = 100
+            symbols = ["FOOBAR", "BARFOO"]
+        "#);
+
+        let api = StockfighterHttpApi::from_config_file(&file.0).unwrap();
+        assert_eq!(api.api_key, "secret");
+        assert_eq!(api.base_url, "https://api.stockfighter.io/ob/api");
+
+        let strategy = StrategyConfig::from_config_file(&file.0).unwrap();
+        assert_eq!(strategy.order_size, Some(100));
+        assert_eq!(strategy.target_spread, None);
+        assert_eq!(strategy.symbols, vec!["FOOBAR", "BARFOO"]);
+    }
+
+    #[test]
+    fn a_missing_api_key_is_named() {
+        let file = ConfigFile::new("missing-key", r#"base_url = "https://api.stockfighter.io/ob/api""#);
+
+        let message = config_error(StockfighterHttpApi::from_config_file(&file.0));
+        assert_eq!(message, "Config is missing required key 'api_key'");
+    }
+
+    #[test]
+    fn a_wrongly_typed_key_is_named() {
+        let file = ConfigFile::new("wrong-type", "api_key = 1234\nbase_url = \"https://api.stockfighter.io/ob/api\"");
+
+        let message = config_error(StockfighterHttpApi::from_config_file(&file.0));
+        assert_eq!(message, "Config key 'api_key' must be a string");
+    }
+
+    #[test]
+    fn malformed_toml_reports_the_line_and_column() {
+        let file = ConfigFile::new("malformed", "api_key = \"secret\"\nbase_url = ");
+
+        let message = config_error(StockfighterHttpApi::from_config_file(&file.0));
+        assert!(message.starts_with("Malformed config file"), "{}", message);
+        assert!(message.ends_with(": 2:12: expected a value"), "{}", message);
+    }
+
+    #[test]
+    fn strategy_must_be_a_table() {
+        let file = ConfigFile::new("strategy-not-table", "api_key = \"secret\"\nstrategy = 5");
+
+        let message = config_error(StrategyConfig::from_config_file(&file.0));
+        assert_eq!(message, "Config key 'strategy' must be a table");
+    }
+
+    #[test]
+    fn negative_integers_are_refused() {
+        let file = ConfigFile::new("negative", "[strategy]\norder_size = -1");
+
+        let message = config_error(StrategyConfig::from_config_file(&file.0));
+        assert_eq!(message, "Config key 'order_size' must be a non-negative integer");
+    }
+}
@@ -0,0 +1,44 @@
+use {StockfighterHttpApi};
+
+
+/// Which deployment of the Stockfighter services to talk to. Each variant bundles
+/// the REST, GM and WebSocket base URLs that belong together.
+#[derive(Clone, Debug)]
+pub enum Environment {
+    Production,
+    Custom {
+        base_url: String,
+        gm_base_url: String,
+        ws_base_url: String,
+    },
+}
+
+impl Environment {
+    pub fn base_url(&self) -> &str {
+        match *self {
+            Environment::Production => "https://api.stockfighter.io/ob/api",
+            Environment::Custom { ref base_url, .. } => base_url,
+        }
+    }
+
+    pub fn gm_base_url(&self) -> &str {
+        match *self {
+            Environment::Production => "https://www.stockfighter.io/gm",
+            Environment::Custom { ref gm_base_url, .. } => gm_base_url,
+        }
+    }
+
+    pub fn ws_base_url(&self) -> &str {
+        match *self {
+            Environment::Production => "wss://api.stockfighter.io/ob/api/ws",
+            Environment::Custom { ref ws_base_url, .. } => ws_base_url,
+        }
+    }
+}
+
+
+impl StockfighterHttpApi {
+    pub fn for_environment<S: Into<String>>(env: &Environment, api_key: S) -> StockfighterHttpApi {
+        StockfighterHttpApi::new(env.base_url(), api_key)
+    }
+}
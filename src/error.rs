@@ -0,0 +1,85 @@
+use hyper;
+use serde_json;
+use std::error::{Error};
+use std::fmt;
+use std::io;
+
+
+#[derive(Debug)]
+pub enum StockfighterError {
+    /// The request couldn't be sent, or no response came back.
+    Http(hyper::Error),
+    /// The connection failed partway through reading the response body.
+    Read { bytes_read: usize, error: io::Error },
+    /// The response body wasn't JSON. Carries the body's length and its first
+    /// few hundred characters, to tell a truncated body from an unexpected one.
+    Json { error: serde_json::Error, body_len: usize, body_prefix: String },
+    /// The response declared a non-JSON Content-Type, e.g. an HTML error page.
+    /// Carries the first few hundred characters of the body.
+    UnexpectedContentType { content_type: String, body_prefix: String },
+    /// The response was JSON, but not in the shape the endpoint promises.
+    InvalidResponse(String),
+    /// The response body exceeded the client's `max_response_bytes`.
+    ResponseTooLarge(u64),
+    /// The API key was missing, wrong or expired.
+    Unauthorized(String),
+    /// The server (or the gateway in front of it) failed with a 5xx status. The
+    /// message is the API's `error` field, or else the start of the body.
+    ServerError { status: u16, message: Option<String> },
+    /// The API understood the request and refused it with this message.
+    Api(String),
+    /// A venue or stock code wasn't in canonical form (trimmed, uppercase) and the
+    /// client is set to `SymbolNormalization::Strict`.
+    InvalidSymbol(String),
+    /// No answer within the allotted time.
+    Timeout(String),
+    /// The client's configuration couldn't be loaded.
+    Config(String),
+}
+
+impl fmt::Display for StockfighterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StockfighterError::Http(ref err) => write!(f, "Error sending request: {}", err),
+            StockfighterError::Read { bytes_read, ref error } => {
+                write!(f, "Error reading response body after {} bytes: {}", bytes_read, error)
+            },
+            StockfighterError::Json { ref error, body_len, ref body_prefix } => {
+                write!(f, "Response body invalid ({}; {} bytes, starting {:?})", error, body_len, body_prefix)
+            },
+            StockfighterError::UnexpectedContentType { ref content_type, ref body_prefix } => {
+                write!(f, "Expected a JSON response but got {} (starting {:?})", content_type, body_prefix)
+            },
+            StockfighterError::InvalidResponse(ref message) => write!(f, "Unexpected response: {}", message),
+            StockfighterError::ResponseTooLarge(limit) => write!(f, "Response too large: exceeded {} bytes", limit),
+            StockfighterError::Unauthorized(ref message) => write!(f, "Unauthorized (check your API key): {}", message),
+            StockfighterError::ServerError { status, message: Some(ref message) } => {
+                write!(f, "Server error: HTTP {}: {}", status, message)
+            },
+            StockfighterError::ServerError { status, message: None } => write!(f, "Server error: HTTP {}", status),
+            StockfighterError::Api(ref message) => write!(f, "{}", message),
+            StockfighterError::InvalidSymbol(ref code) => {
+                write!(f, "Invalid symbol {:?}: codes must be trimmed and uppercase", code)
+            },
+            StockfighterError::Timeout(ref message) => write!(f, "Timed out: {}", message),
+            StockfighterError::Config(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for StockfighterError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            StockfighterError::Http(ref err) => Some(err),
+            StockfighterError::Read { ref error, .. } => Some(error),
+            StockfighterError::Json { ref error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<hyper::Error> for StockfighterError {
+    fn from(err: hyper::Error) -> StockfighterError {
+        StockfighterError::Http(err)
+    }
+}
@@ -0,0 +1,350 @@
+//! A minimal C ABI over the HTTP client, enabled by the `ffi` feature.
+//!
+//! Clients and orderbooks are opaque pointers, created and destroyed only through
+//! these functions. Fallible calls return one of the `SF_*` status codes. On
+//! `SF_ERROR`, and only if `error` is non-null, `*error` is set to a message the
+//! caller must release with `sf_string_free`.
+//!
+//! Only the endpoints this crate wraps are exposed: heartbeats and orderbooks.
+//! To get a shared library, build with e.g.
+//! `cargo rustc --features ffi -- --crate-type=cdylib`.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use {Order, Orderbook, StockfighterAPI, StockfighterError, StockfighterHttpApi, StockfighterResult};
+
+
+pub const SF_OK: c_int = 0;
+pub const SF_ERROR: c_int = 1;
+pub const SF_INVALID_ARGUMENT: c_int = -1;
+
+
+/// Returns null if either argument is null or not UTF-8.
+///
+/// # Safety
+///
+/// `base_url` and `api_key` must each be null or a valid NUL-terminated string.
+/// They are copied, so they need only live for the duration of the call. The
+/// returned client is owned by the caller and must be released with
+/// `sf_client_free`.
+#[no_mangle]
+pub unsafe extern "C" fn sf_client_new(base_url: *const c_char, api_key: *const c_char) -> *mut StockfighterHttpApi {
+    match (to_str(base_url), to_str(api_key)) {
+        (Some(base_url), Some(api_key)) => Box::into_raw(Box::new(StockfighterHttpApi::new(base_url, api_key))),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `client` must be null or a pointer returned by `sf_client_new` that hasn't
+/// been freed yet. It is invalid after this call.
+#[no_mangle]
+pub unsafe extern "C" fn sf_client_free(client: *mut StockfighterHttpApi) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
+
+/// # Safety
+///
+/// `client` must be null or a live pointer from `sf_client_new`. `error` must be
+/// null or valid for writing one pointer.
+#[no_mangle]
+pub unsafe extern "C" fn sf_heartbeat(client: *const StockfighterHttpApi, error: *mut *mut c_char) -> c_int {
+    let client = match client.as_ref() {
+        Some(client) => client,
+        None => return SF_INVALID_ARGUMENT,
+    };
+
+    status(guard(|| client.heartbeat()), error).0
+}
+
+/// # Safety
+///
+/// `client` must be null or a live pointer from `sf_client_new`, `venue` null or
+/// a valid NUL-terminated string, and `error` null or valid for writing one
+/// pointer.
+#[no_mangle]
+pub unsafe extern "C" fn sf_venue_heartbeat(client: *const StockfighterHttpApi,
+                                            venue: *const c_char,
+                                            error: *mut *mut c_char) -> c_int {
+    let (client, venue) = match (client.as_ref(), to_str(venue)) {
+        (Some(client), Some(venue)) => (client, venue),
+        _ => return SF_INVALID_ARGUMENT,
+    };
+
+    status(guard(|| client.venue_heartbeat(venue)), error).0
+}
+
+/// On success, `*book` receives an orderbook to be released with `sf_orderbook_free`.
+///
+/// # Safety
+///
+/// `client` must be null or a live pointer from `sf_client_new`. `venue` and
+/// `stock` must be null or valid NUL-terminated strings. `book` and `error`
+/// must each be null or valid for writing one pointer.
+#[no_mangle]
+pub unsafe extern "C" fn sf_stock_orderbook(client: *const StockfighterHttpApi,
+                                            venue: *const c_char,
+                                            stock: *const c_char,
+                                            book: *mut *mut Orderbook,
+                                            error: *mut *mut c_char) -> c_int {
+    if book.is_null() {
+        return SF_INVALID_ARGUMENT;
+    }
+    let (client, venue, stock) = match (client.as_ref(), to_str(venue), to_str(stock)) {
+        (Some(client), Some(venue), Some(stock)) => (client, venue, stock),
+        _ => return SF_INVALID_ARGUMENT,
+    };
+
+    match status(guard(|| client.stock_orderbook(venue, stock)), error) {
+        (code, Some(result)) => {
+            *book = Box::into_raw(Box::new(result));
+            code
+        },
+        (code, None) => code,
+    }
+}
+
+/// # Safety
+///
+/// `book` must be null or a pointer received from `sf_stock_orderbook` that
+/// hasn't been freed yet. It is invalid after this call.
+#[no_mangle]
+pub unsafe extern "C" fn sf_orderbook_free(book: *mut Orderbook) {
+    if !book.is_null() {
+        drop(Box::from_raw(book));
+    }
+}
+
+/// # Safety
+///
+/// `book` must be null or a live pointer from `sf_stock_orderbook`.
+#[no_mangle]
+pub unsafe extern "C" fn sf_orderbook_bid_count(book: *const Orderbook) -> usize {
+    book.as_ref().map_or(0, |book| book.bids.len())
+}
+
+/// # Safety
+///
+/// `book` must be null or a live pointer from `sf_stock_orderbook`.
+#[no_mangle]
+pub unsafe extern "C" fn sf_orderbook_ask_count(book: *const Orderbook) -> usize {
+    book.as_ref().map_or(0, |book| book.asks.len())
+}
+
+/// # Safety
+///
+/// `book` must be null or a live pointer from `sf_stock_orderbook`. `price` and
+/// `qty` must be null or valid for writing a `u64`; they are only written on
+/// `SF_OK`.
+#[no_mangle]
+pub unsafe extern "C" fn sf_orderbook_bid(book: *const Orderbook, index: usize, price: *mut u64, qty: *mut u64) -> c_int {
+    match book.as_ref() {
+        Some(book) => read_order(&book.bids, index, price, qty),
+        None => SF_INVALID_ARGUMENT,
+    }
+}
+
+/// # Safety
+///
+/// As for `sf_orderbook_bid`.
+#[no_mangle]
+pub unsafe extern "C" fn sf_orderbook_ask(book: *const Orderbook, index: usize, price: *mut u64, qty: *mut u64) -> c_int {
+    match book.as_ref() {
+        Some(book) => read_order(&book.asks, index, price, qty),
+        None => SF_INVALID_ARGUMENT,
+    }
+}
+
+/// The orderbook's timestamp, in whole seconds since the Unix epoch.
+///
+/// # Safety
+///
+/// `book` must be null or a live pointer from `sf_stock_orderbook`.
+#[no_mangle]
+pub unsafe extern "C" fn sf_orderbook_timestamp(book: *const Orderbook) -> i64 {
+    book.as_ref().map_or(0, |book| book.timestamp.timestamp())
+}
+
+/// # Safety
+///
+/// `string` must be null or a message this library wrote through an `error`
+/// out-parameter, not yet freed. Strings allocated any other way must not be
+/// passed here.
+#[no_mangle]
+pub unsafe extern "C" fn sf_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+
+unsafe fn to_str<'a>(string: *const c_char) -> Option<&'a str> {
+    if string.is_null() {
+        return None;
+    }
+    CStr::from_ptr(string).to_str().ok()
+}
+
+// Unwinding into C is undefined behaviour, so should anything below panic after
+// all, turn that into an ordinary error at the boundary.
+fn guard<T, F: FnOnce() -> StockfighterResult<T>>(f: F) -> StockfighterResult<T> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(_) => Err(StockfighterError::InvalidResponse("internal error while handling the response".to_owned())),
+    }
+}
+
+unsafe fn status<T>(result: StockfighterResult<T>, error: *mut *mut c_char) -> (c_int, Option<T>) {
+    match result {
+        Ok(value) => (SF_OK, Some(value)),
+        Err(err) => {
+            if !error.is_null() {
+                // Messages come from us or the server; don't let a stray NUL lose them.
+                let message = err.to_string().replace('\0', " ");
+                *error = CString::new(message).unwrap().into_raw();
+            }
+            (SF_ERROR, None)
+        },
+    }
+}
+
+unsafe fn read_order(orders: &[Order], index: usize, price: *mut u64, qty: *mut u64) -> c_int {
+    let order = match orders.get(index) {
+        Some(order) => order,
+        None => return SF_INVALID_ARGUMENT,
+    };
+    if price.is_null() || qty.is_null() {
+        return SF_INVALID_ARGUMENT;
+    }
+
+    *price = order.price;
+    *qty = order.qty;
+    SF_OK
+}
+
+
+#[cfg(all(test, feature = "ffi"))]
+mod tests {
+    use std::ffi::{CStr, CString};
+    use std::os::raw::{c_char};
+    use std::ptr;
+
+    use mock_server::{CannedResponse, MockServer};
+    use super::*;
+
+    fn server() -> MockServer {
+        MockServer::start(vec![
+            ("/heartbeat", CannedResponse::json("200 OK", r#"{"ok": true, "error": ""}"#)),
+            ("/venues/TESTEX/stocks/FOOBAR", CannedResponse::json("200 OK", r#"{"ok": true,
+                "bids": [{"price": 5100, "qty": 10, "isBuy": true}, {"price": 5050, "qty": 2, "isBuy": true}],
+                "asks": [{"price": 5200, "qty": 7, "isBuy": false}],
+                "ts": "2015-12-04T09:02:16.680986205Z"}"#)),
+        ])
+    }
+
+    fn client(server: &MockServer) -> *mut StockfighterHttpApi {
+        let base_url = CString::new(server.base_url()).unwrap();
+        let api_key = CString::new("secret").unwrap();
+        let client = unsafe { sf_client_new(base_url.as_ptr(), api_key.as_ptr()) };
+        assert!(!client.is_null());
+        client
+    }
+
+    #[test]
+    fn null_arguments_are_invalid() {
+        let server = server();
+        let client = client(&server);
+        let venue = CString::new("TESTEX").unwrap();
+        let mut book = ptr::null_mut();
+        let mut price = 0;
+        let mut qty = 0;
+
+        unsafe {
+            assert!(sf_client_new(ptr::null(), venue.as_ptr()).is_null());
+            assert!(sf_client_new(venue.as_ptr(), ptr::null()).is_null());
+
+            assert_eq!(sf_heartbeat(ptr::null(), ptr::null_mut()), SF_INVALID_ARGUMENT);
+            assert_eq!(sf_venue_heartbeat(client, ptr::null(), ptr::null_mut()), SF_INVALID_ARGUMENT);
+            assert_eq!(sf_stock_orderbook(client, venue.as_ptr(), ptr::null(), &mut book, ptr::null_mut()), SF_INVALID_ARGUMENT);
+            assert_eq!(sf_stock_orderbook(client, venue.as_ptr(), venue.as_ptr(), ptr::null_mut(), ptr::null_mut()), SF_INVALID_ARGUMENT);
+            assert!(book.is_null());
+
+            assert_eq!(sf_orderbook_bid_count(ptr::null()), 0);
+            assert_eq!(sf_orderbook_ask_count(ptr::null()), 0);
+            assert_eq!(sf_orderbook_bid(ptr::null(), 0, &mut price, &mut qty), SF_INVALID_ARGUMENT);
+            assert_eq!(sf_orderbook_ask(ptr::null(), 0, &mut price, &mut qty), SF_INVALID_ARGUMENT);
+            assert_eq!(sf_orderbook_timestamp(ptr::null()), 0);
+
+            sf_orderbook_free(ptr::null_mut());
+            sf_string_free(ptr::null_mut());
+            sf_client_free(client);
+            sf_client_free(ptr::null_mut());
+        }
+        assert!(server.requests().is_empty());
+    }
+
+    #[test]
+    fn errors_are_reported_through_the_out_parameter() {
+        let server = server();
+        let client = client(&server);
+        let venue = CString::new("NOPE").unwrap();
+        let mut error: *mut c_char = ptr::null_mut();
+
+        unsafe {
+            assert_eq!(sf_heartbeat(client, &mut error), SF_OK);
+            assert!(error.is_null());
+
+            assert_eq!(sf_venue_heartbeat(client, venue.as_ptr(), &mut error), SF_ERROR);
+            assert!(!error.is_null());
+            assert_eq!(CStr::from_ptr(error).to_str().unwrap(), "not found");
+            sf_string_free(error);
+
+            // Without somewhere to put it, the message is just dropped.
+            assert_eq!(sf_venue_heartbeat(client, venue.as_ptr(), ptr::null_mut()), SF_ERROR);
+
+            sf_client_free(client);
+        }
+    }
+
+    #[test]
+    fn orderbooks_are_read_through_their_handle() {
+        let server = server();
+        let client = client(&server);
+        let venue = CString::new("TESTEX").unwrap();
+        let stock = CString::new("FOOBAR").unwrap();
+        let mut book = ptr::null_mut();
+        let mut error: *mut c_char = ptr::null_mut();
+        let (mut price, mut qty) = (0, 0);
+
+        unsafe {
+            assert_eq!(sf_stock_orderbook(client, venue.as_ptr(), stock.as_ptr(), &mut book, &mut error), SF_OK);
+            assert!(!book.is_null());
+            assert!(error.is_null());
+
+            assert_eq!(sf_orderbook_bid_count(book), 2);
+            assert_eq!(sf_orderbook_ask_count(book), 1);
+
+            assert_eq!(sf_orderbook_bid(book, 1, &mut price, &mut qty), SF_OK);
+            assert_eq!((price, qty), (5050, 2));
+            assert_eq!(sf_orderbook_ask(book, 0, &mut price, &mut qty), SF_OK);
+            assert_eq!((price, qty), (5200, 7));
+
+            // Out of range, or nowhere to write: nothing is written.
+            assert_eq!(sf_orderbook_bid(book, 2, &mut price, &mut qty), SF_INVALID_ARGUMENT);
+            assert_eq!(sf_orderbook_ask(book, 1, &mut price, &mut qty), SF_INVALID_ARGUMENT);
+            assert_eq!(sf_orderbook_bid(book, 0, ptr::null_mut(), &mut qty), SF_INVALID_ARGUMENT);
+            assert_eq!((price, qty), (5200, 7));
+
+            assert_eq!(sf_orderbook_timestamp(book), 1449219736);
+
+            sf_orderbook_free(book);
+            sf_client_free(client);
+        }
+    }
+}
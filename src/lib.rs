@@ -2,14 +2,57 @@
 extern crate hyper;
 extern crate serde_json;
 extern crate chrono;
+#[cfg(feature = "config")]
+extern crate toml;
 
 use hyper::{Client};
+use hyper::client::{pool};
+use hyper::header::{ContentType, Headers};
+use hyper::status::{StatusCode};
 use serde_json::{Value};
+use chrono::{Duration};
 use chrono::naive::datetime::{NaiveDateTime};
-use std::iter::{FromIterator};
+use std::cmp::{self, Ordering};
+use std::collections::{HashMap};
+use std::fmt;
+use std::io::{Read};
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::mpsc::{RecvTimeoutError};
+use std::thread;
 
+pub mod error;
+pub use error::{StockfighterError};
+pub mod book;
+pub use book::{Level, L2Book, MarketSnapshot, OrderbookBuilder};
+pub mod clock;
+pub use clock::{Clock, SystemClock, MockClock};
+pub mod environment;
+pub use environment::{Environment};
+pub mod recording;
+pub use recording::{write_snapshots, read_snapshots, Recording};
+pub mod monitor;
+pub use monitor::{HeartbeatMonitor, wait_until_ready};
+pub mod queue;
+pub use queue::{QueueEstimator, QueueRank, queue_rank};
+pub mod sim;
+pub use sim::{SimulatedVenue, SimOrderType, SimFill, SimOrderResult};
+pub mod spread;
+pub use spread::{SpreadMonitor};
+#[cfg(feature = "config")]
+pub mod config;
+#[cfg(feature = "config")]
+pub use config::{StrategyConfig};
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "metrics")]
+pub use metrics::{EndpointMetrics, LatencySummary};
+#[cfg(test)]
+mod mock_server;
 
-#[derive(Debug)]
+
+#[derive(Clone, Debug)]
 pub struct VenueInfo {
     pub id: u64,
     pub name: String,
@@ -17,14 +60,29 @@ pub struct VenueInfo {
     pub venue: String,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Order {
     pub price: u64,
     pub qty: u64,
     pub is_buy: bool,
 }
 
-#[derive(Debug)]
+// `Order` deliberately has no `Ord`: "better" means a higher price for bids but a
+// lower one for asks, so any single ordering would be wrong for half the book.
+// Sort with the side-specific comparators instead, e.g. `bids.sort_by(Order::bid_priority)`.
+impl Order {
+    /// Best bid first: highest price.
+    pub fn bid_priority(a: &Order, b: &Order) -> Ordering {
+        b.price.cmp(&a.price)
+    }
+
+    /// Best ask first: lowest price.
+    pub fn ask_priority(a: &Order, b: &Order) -> Ordering {
+        a.price.cmp(&b.price)
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Orderbook {
     pub bids: Vec<Order>,
     pub asks: Vec<Order>,
@@ -32,148 +90,1182 @@ pub struct Orderbook {
 }
 
 
-pub type StockfighterResult<T> = Result<T, String>;
+/// How venue and stock codes are treated before they go into a request URL. The
+/// API is case-sensitive, so a stray space or lowercase letter otherwise turns
+/// into a confusing "not found".
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SymbolNormalization {
+    /// Send codes exactly as given.
+    Off,
+    /// Trim surrounding whitespace and uppercase.
+    Normalize,
+    /// Reject any code that `Normalize` would change, with `InvalidSymbol`.
+    Strict,
+}
+
+
+/// Hooks run on every request the client makes, e.g. to add headers, sign
+/// requests, or doctor responses in tests. Register with `with_interceptor`, or
+/// pass a closure to `with_request_hook` or `with_response_hook`.
+pub trait Interceptor: Send + Sync {
+    /// Called with the URL and headers of each outgoing request, before it's sent.
+    fn on_request(&self, _url: &mut String, _headers: &mut Headers) {}
+
+    /// Called with each response's status and raw body, before the body is parsed.
+    fn on_response(&self, _status: StatusCode, _body: &mut String) {}
+}
+
+
+/// A response as returned by `send_raw_verbose`.
+#[derive(Debug)]
+pub struct VerboseResponse {
+    pub request: String,
+    pub status: String,
+    pub body: String,
+    pub json: StockfighterResult<Value>,
+}
+
+
+pub type StockfighterResult<T> = Result<T, StockfighterError>;
 pub trait StockfighterAPI {
     fn heartbeat(&self) -> StockfighterResult<()>;
     fn venues(&self) -> StockfighterResult<Vec<VenueInfo>>;
 
+    /// `venues()`, keyed by venue code as the API lists it; pass user input
+    /// through `normalize_symbol` before looking it up. If the API ever lists a
+    /// code twice, the later entry wins.
+    fn venues_map(&self) -> StockfighterResult<HashMap<String, VenueInfo>> {
+        let venues = self.venues()?;
+        Ok(venues.into_iter().map(|info| (info.venue.clone(), info)).collect())
+    }
+
     fn venue_heartbeat(&self, venue: &str) -> StockfighterResult<()>;
     fn stock_orderbook(&self, venue: &str, stock: &str) -> StockfighterResult<Orderbook>;
+
+    /// The canonical form of a venue or stock code, as the implementor's own
+    /// methods would use it. By default, codes are used exactly as given.
+    fn normalize_symbol(&self, code: &str) -> StockfighterResult<String> {
+        Ok(code.to_owned())
+    }
+
+    /// Whether `stock` can be traded right now: the venue is listed as open and the
+    /// book has both a bid and an ask. An unlisted venue counts as not tradeable.
+    fn is_tradeable(&self, venue: &str, stock: &str) -> StockfighterResult<bool> {
+        let venue = self.normalize_symbol(venue)?;
+        let stock = self.normalize_symbol(stock)?;
+
+        let venues = self.venues()?;
+        if !venues.iter().any(|info| info.venue == venue && info.is_open) {
+            return Ok(false);
+        }
+
+        let book = self.stock_orderbook(&venue, &stock)?;
+        Ok(!book.bids.is_empty() && !book.asks.is_empty())
+    }
 }
 
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct StockfighterHttpApi {
-    pub base_url: &'static str,
-    pub api_key: &'static str,
+    pub base_url: String,
+    pub api_key: String,
+
+    /// Keep only the best N price levels on each side of a fetched orderbook.
+    /// Anything computed from the book then describes the displayed top-N
+    /// levels, not the venue's full depth.
+    pub max_levels: Option<usize>,
+
+    /// Refuse to buffer response bodies larger than this many bytes. `u64::MAX`
+    /// effectively turns the cap off.
+    pub max_response_bytes: u64,
+
+    /// Idle connections kept open per host by the connection pool.
+    pub pool_max_idle: usize,
+    /// Drop every pooled connection once the client has gone this long without
+    /// making a request. hyper can't expire connections one at a time, so this
+    /// applies to the pool as a whole.
+    pub pool_idle_timeout: Option<Duration>,
+
+    /// Make `venue_heartbeat` fail unless the response names the venue that was
+    /// asked for, to catch a wrong base URL or a mistyped venue code.
+    pub strict_venue_check: bool,
+
+    /// Applied to venue and stock codes passed to the trait methods. Defaults to
+    /// `SymbolNormalization::Normalize`.
+    pub symbol_normalization: SymbolNormalization,
+
+    // Run in registration order on every request and response.
+    interceptors: Interceptors,
+
+    // Times the connection pool's idleness, heartbeats and fan-out deadlines.
+    // `SystemClock` unless replaced with `with_clock`.
+    clock: SharedClock,
+
+    // Shared between clones, so they all draw on one connection pool.
+    client: Arc<SharedClient>,
+
+    // Shared between clones, so a client handed to other threads still reports here.
+    #[cfg(feature = "metrics")]
+    metrics: Arc<Mutex<EndpointMetrics>>,
+}
+
+// The client is meant to be shared across threads (e.g. behind an `Arc`), so
+// make losing `Send + Sync` a compile error rather than a surprise downstream.
+#[allow(dead_code)]
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[allow(dead_code)]
+fn assert_client_send_sync() {
+    assert_send_sync::<StockfighterHttpApi>();
+    assert_send_sync::<HeartbeatMonitor>();
 }
 
+// Likewise for the public data types being `Clone + Debug`, so they can go
+// into collections and log lines. The exceptions are `StockfighterError` and
+// `VerboseResponse`, which wrap hyper and io errors, and the handles that own
+// a thread or a venue's state (`HeartbeatMonitor`, `SimulatedVenue`).
+#[allow(dead_code)]
+fn assert_clone_debug<T: Clone + fmt::Debug>() {}
+
+#[allow(dead_code)]
+fn assert_data_clone_debug() {
+    assert_clone_debug::<VenueInfo>();
+    assert_clone_debug::<Order>();
+    assert_clone_debug::<Orderbook>();
+    assert_clone_debug::<Level>();
+    assert_clone_debug::<L2Book>();
+    assert_clone_debug::<MarketSnapshot>();
+    assert_clone_debug::<OrderbookBuilder>();
+    assert_clone_debug::<Recording>();
+    assert_clone_debug::<QueueEstimator>();
+    assert_clone_debug::<QueueRank>();
+    assert_clone_debug::<SpreadMonitor>();
+    assert_clone_debug::<SimOrderType>();
+    assert_clone_debug::<SimFill>();
+    assert_clone_debug::<SimOrderResult>();
+    assert_clone_debug::<SymbolNormalization>();
+    assert_clone_debug::<Environment>();
+    assert_clone_debug::<StockfighterHttpApi>();
+    #[cfg(feature = "config")]
+    assert_clone_debug::<StrategyConfig>();
+    #[cfg(feature = "metrics")]
+    assert_clone_debug::<EndpointMetrics>();
+}
+
+#[derive(Clone)]
+struct Interceptors(Vec<Arc<dyn Interceptor>>);
+
+impl fmt::Debug for Interceptors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Interceptors({})", self.0.len())
+    }
+}
+
+// The closures behind `with_request_hook` and `with_response_hook`.
+struct RequestHook<F>(F);
+
+impl<F> Interceptor for RequestHook<F> where F: Fn(&mut String, &mut Headers) + Send + Sync {
+    fn on_request(&self, url: &mut String, headers: &mut Headers) {
+        (self.0)(url, headers)
+    }
+}
+
+struct ResponseHook<F>(F);
+
+impl<F> Interceptor for ResponseHook<F> where F: Fn(StatusCode, &mut String) + Send + Sync {
+    fn on_response(&self, status: StatusCode, body: &mut String) {
+        (self.0)(status, body)
+    }
+}
+
+#[derive(Clone)]
+struct SharedClock(Arc<dyn Clock>);
+
+impl fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedClock")
+    }
+}
+
+#[derive(Default)]
+struct SharedClient {
+    pooled: Mutex<Option<PooledClient>>,
+}
+
+struct PooledClient {
+    client: Arc<Client>,
+    max_idle: usize,
+    last_used: NaiveDateTime,
+}
+
+impl fmt::Debug for SharedClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedClient")
+    }
+}
+
+pub const DEFAULT_MAX_RESPONSE_BYTES: u64 = 16 * 1024 * 1024;
+
 header! { (XStarfighterAuthorization, "X-Starfighter-Authorization") => [String] }
 
 impl StockfighterHttpApi {
-    #[allow(unused_parens)]
+    pub fn new<S1, S2>(base_url: S1, api_key: S2) -> StockfighterHttpApi
+        where S1: Into<String>, S2: Into<String>
+    {
+        StockfighterHttpApi {
+            base_url: base_url.into(),
+            api_key: api_key.into(),
+            max_levels: None,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            pool_max_idle: pool::Config::default().max_idle,
+            pool_idle_timeout: None,
+            strict_venue_check: false,
+            symbol_normalization: SymbolNormalization::Normalize,
+            interceptors: Interceptors(Vec::new()),
+            clock: SharedClock(Arc::new(SystemClock)),
+            client: Arc::new(SharedClient::default()),
+            #[cfg(feature = "metrics")]
+            metrics: Arc::new(Mutex::new(EndpointMetrics::default())),
+        }
+    }
+
+    /// A copy of this client with the same credentials and settings, pointed at another server.
+    pub fn with_base_url<S: Into<String>>(&self, base_url: S) -> StockfighterHttpApi {
+        StockfighterHttpApi { base_url: base_url.into(), ..self.clone() }
+    }
+
+    /// A copy of this client that tells time by `clock`, e.g. a `MockClock`. Clones
+    /// share one connection pool, so its idleness is timed by whichever clock
+    /// the last request came through.
+    pub fn with_clock<C: Clock + 'static>(&self, clock: C) -> StockfighterHttpApi {
+        StockfighterHttpApi { clock: SharedClock(Arc::new(clock)), ..self.clone() }
+    }
+
+    /// A copy of this client that also runs `interceptor`, after any it already has.
+    pub fn with_interceptor<I: Interceptor + 'static>(&self, interceptor: I) -> StockfighterHttpApi {
+        let mut api = self.clone();
+        api.interceptors.0.push(Arc::new(interceptor));
+        api
+    }
+
+    /// A copy of this client that also passes each outgoing request's URL and
+    /// headers to `hook`; see `Interceptor::on_request`.
+    pub fn with_request_hook<F>(&self, hook: F) -> StockfighterHttpApi
+        where F: Fn(&mut String, &mut Headers) + Send + Sync + 'static
+    {
+        self.with_interceptor(RequestHook(hook))
+    }
+
+    /// A copy of this client that also passes each response's status and raw
+    /// body to `hook`; see `Interceptor::on_response`.
+    pub fn with_response_hook<F>(&self, hook: F) -> StockfighterHttpApi
+        where F: Fn(StatusCode, &mut String) + Send + Sync + 'static
+    {
+        self.with_interceptor(ResponseHook(hook))
+    }
+
     pub fn send_raw(&self, path: &str) -> StockfighterResult<Value> {
-        let url = format!("{}{}", self.base_url, path);
+        self.receive(path).map(|(_, json)| json)
+    }
+
+    // `send_raw`, also keeping the HTTP status for `check_ok`.
+    fn receive(&self, path: &str) -> StockfighterResult<(StatusCode, Value)> {
+        let response = self.fetch(path)?;
+        let json = parse_body(&response.body);
+
+        // A bad or expired key deserves a clear message up front, rather than
+        // surfacing as whatever the endpoint's own parsing makes of the body.
+        let message = json.as_ref().ok()
+            .and_then(|json| json.find("error"))
+            .and_then(|error| error.as_string())
+            .map(|error| error.to_owned());
+        let auth_failed = response.status == StatusCode::Unauthorized
+            || message.as_deref().is_some_and(is_auth_error);
+        if auth_failed {
+            let message = message.unwrap_or_else(|| response.status_line.clone());
+            return Err(StockfighterError::Unauthorized(message));
+        }
+
+        // Gateway failures come back as HTML error pages; report the status
+        // rather than a confusing JSON parse error, keeping whatever the server
+        // said: its `error` message if it sent JSON, or else the start of the body.
+        if response.status.is_server_error() {
+            let message = message.or_else(|| {
+                let prefix = body_prefix(&response.body).trim();
+                if prefix.is_empty() { None } else { Some(prefix.to_owned()) }
+            });
+            return Err(StockfighterError::ServerError { status: response.status.to_u16(), message });
+        }
+
+        // Likewise, name an HTML page from a proxy or captive portal for what it
+        // is. A response without a Content-Type is left to the parser.
+        if let Some(ref content_type) = response.content_type {
+            if !is_json_content_type(content_type) {
+                return Err(StockfighterError::UnexpectedContentType {
+                    content_type: content_type.clone(),
+                    body_prefix: body_prefix(&response.body).to_owned(),
+                });
+            }
+        }
+
+        let json = json?;
+        Ok((response.status, json))
+    }
+
+    /// GETs an arbitrary endpoint and checks the API's `ok` flag, returning the
+    /// JSON untouched. For endpoints (or fields) the typed methods don't cover.
+    pub fn get_json(&self, path: &str) -> StockfighterResult<Value> {
+        let (status, response) = self.receive(path)?;
+        check_ok(&response, "ok", status)?;
+        Ok(response)
+    }
+
+    /// Like `send_raw`, but also returns the request line, the response's status
+    /// line and its raw body. Useful for poking at endpoints by hand; the body is
+    /// returned even if it doesn't parse.
+    pub fn send_raw_verbose(&self, path: &str) -> StockfighterResult<VerboseResponse> {
+        let response = self.fetch(path)?;
+        let json = parse_body(&response.body);
+
+        Ok(VerboseResponse {
+            request: format!("GET {}", response.url),
+            status: response.status_line,
+            body: response.body,
+            json,
+        })
+    }
+
+    #[allow(unused_parens)]
+    fn fetch(&self, path: &str) -> StockfighterResult<RawResponse> {
+        let mut url = format!("{}{}", self.base_url, path);
+        let mut headers = Headers::new();
+        headers.set(XStarfighterAuthorization(self.api_key.clone()));
+        for interceptor in &self.interceptors.0 {
+            interceptor.on_request(&mut url, &mut headers);
+        }
 
-        let client = Client::new();
+        let client = self.pooled_client();
         let req =
             ( client
             . get(&url)
-            . header(XStarfighterAuthorization(self.api_key.to_owned()))
+            . headers(headers)
             );
 
-        let mut res = match req.send() {
-            Ok(res) => res,
-            Err(_) => return Err("Error sending request".to_owned()),
+        let mut res = req.send()?;
+
+        // Buffer the whole body first, so that a parse failure can say what we
+        // actually received -- a connection dropped mid-response looks very
+        // different from a body that's complete but not what we expected.
+        let mut bytes = Vec::new();
+        if let Err(err) = res.by_ref().take(self.max_response_bytes.saturating_add(1)).read_to_end(&mut bytes) {
+            return Err(StockfighterError::Read { bytes_read: bytes.len(), error: err });
+        }
+        if bytes.len() as u64 > self.max_response_bytes {
+            return Err(StockfighterError::ResponseTooLarge(self.max_response_bytes));
+        }
+
+        let mut body = match String::from_utf8(bytes) {
+            Ok(body) => body,
+            Err(_) => return Err(StockfighterError::InvalidResponse("body is not valid UTF-8".to_owned())),
+        };
+        for interceptor in &self.interceptors.0 {
+            interceptor.on_response(res.status, &mut body);
+        }
+
+        Ok(RawResponse {
+            url,
+            status: res.status,
+            status_line: format!("{} {}", res.version, res.status),
+            content_type: res.headers.get::<ContentType>().map(|content_type| content_type.to_string()),
+            body,
+        })
+    }
+
+    /// Drops every pooled connection, for this client and all its clones; the next
+    /// request starts a fresh pool with the current settings. Requests already in
+    /// flight finish on their old connections.
+    pub fn reset_connections(&self) {
+        *self.client.pooled.lock().unwrap() = None;
+    }
+
+    // The shared hyper client, (re)built if the pool settings have changed or it
+    // has been idle for longer than `pool_idle_timeout`.
+    fn pooled_client(&self) -> Arc<Client> {
+        let now = self.clock.0.now();
+        let mut pooled = self.client.pooled.lock().unwrap();
+
+        let needs_rebuild = match *pooled {
+            Some(ref pooled) => {
+                pooled.max_idle != self.pool_max_idle
+                    || self.pool_idle_timeout.is_some_and(|timeout| now - pooled.last_used > timeout)
+            },
+            None => true,
         };
+        if needs_rebuild {
+            *pooled = Some(PooledClient {
+                client: Arc::new(Client::with_pool_config(pool::Config { max_idle: self.pool_max_idle })),
+                max_idle: self.pool_max_idle,
+                last_used: now,
+            });
+        }
+
+        let pooled = pooled.as_mut().unwrap();
+        pooled.last_used = now;
+        pooled.client.clone()
+    }
+
+    // Every typed endpoint goes through here, so per-endpoint instrumentation and
+    // status checking have one home.
+    #[cfg(not(feature = "metrics"))]
+    fn send(&self, endpoint: &Endpoint, path: &str) -> StockfighterResult<Value> {
+        let (status, json) = self.receive(path)?;
+        check_ok(&json, endpoint.ok_field, status)?;
+        Ok(json)
+    }
+
+    #[cfg(feature = "metrics")]
+    fn send(&self, endpoint: &Endpoint, path: &str) -> StockfighterResult<Value> {
+        let start = self.clock.0.now();
+        let result = self.receive(path);
+        self.metrics.lock().unwrap().record(endpoint.name, self.clock.0.now() - start);
 
-        let json = match serde_json::from_reader(&mut res) {
-            Ok(json) => Ok(json),
-            Err(_) => return Err("Response body invalid".to_owned()),
+        let (status, json) = result?;
+        check_ok(&json, endpoint.ok_field, status)?;
+        Ok(json)
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> EndpointMetrics {
+        self.metrics.lock().unwrap().clone()
+    }
+
+    /// `venues`, plus the raw entries that were left out for lacking an id, a
+    /// venue code or a known state.
+    pub fn venues_with_skipped(&self) -> StockfighterResult<(Vec<VenueInfo>, Vec<Value>)> {
+        let response = self.send(&VENUES, "/venues")?;
+
+        let entries = match response.find("venues").and_then(|venues| venues.as_array()) {
+            Some(entries) => entries,
+            None => return Err(StockfighterError::InvalidResponse("no 'venues' array".to_owned())),
         };
 
-        // println!("{:#?}", json);
-        json
+        // One malformed record (e.g. mid-maintenance) shouldn't cost us the rest of the list.
+        let mut venues = Vec::new();
+        let mut skipped = Vec::new();
+        for entry in entries {
+            match parse_venue(entry) {
+                Some(venue) => venues.push(venue),
+                None => skipped.push(entry.clone()),
+            }
+        }
+
+        Ok((venues, skipped))
+    }
+
+    /// `heartbeat`, returning how long the round trip took.
+    pub fn heartbeat_timed(&self) -> StockfighterResult<Duration> {
+        let start = self.clock.0.now();
+        self.heartbeat()?;
+        Ok(self.clock.0.now() - start)
+    }
+
+    /// `venue_heartbeat`, returning how long the round trip took.
+    pub fn venue_heartbeat_timed(&self, venue: &str) -> StockfighterResult<Duration> {
+        let start = self.clock.0.now();
+        self.venue_heartbeat(venue)?;
+        Ok(self.clock.0.now() - start)
+    }
+
+    pub fn start_heartbeat_monitor(&self, venue: &str, interval: Duration) -> HeartbeatMonitor {
+        HeartbeatMonitor::start(self.clone(), venue, interval, self.clock.0.clone())
+    }
+
+    /// Blocks until the API and `venue` both answer a heartbeat; see `monitor::wait_until_ready`.
+    pub fn wait_until_ready(&self, venue: &str, timeout: Duration) -> StockfighterResult<()> {
+        wait_until_ready(self, venue, timeout, &self.clock.0)
+    }
+
+    /// Heartbeats every venue concurrently. Results come back in input order; a venue
+    /// that hasn't answered within `per_venue_timeout`, as measured on the client's
+    /// clock, reports a timeout error.
+    ///
+    /// Timed-out heartbeats aren't cancelled: each runs on a thread of its own,
+    /// which carries on (holding its connection) until hyper returns.
+    pub fn venue_heartbeat_all(&self, venues: &[&str], per_venue_timeout: Duration) -> Vec<(String, StockfighterResult<()>)> {
+        let (tx, rx) = mpsc::channel();
+        for (i, venue) in venues.iter().enumerate() {
+            let api = self.clone();
+            let venue = (*venue).to_owned();
+            let tx = tx.clone();
+            thread::spawn(move || {
+                let _ = tx.send((i, api.venue_heartbeat(&venue)));
+            });
+        }
+        drop(tx);
+
+        // Every heartbeat starts at the same moment, so they share a deadline. It's
+        // measured on the client's clock, checked at least this often.
+        let poll = Duration::milliseconds(50);
+        let deadline = self.clock.0.now() + per_venue_timeout;
+        let mut results: Vec<Option<StockfighterResult<()>>> = venues.iter().map(|_| None).collect();
+        let mut pending = venues.len();
+        while pending > 0 {
+            let now = self.clock.0.now();
+            if now >= deadline {
+                break;
+            }
+
+            let wait = cmp::min(deadline - now, poll).to_std().unwrap();
+            match rx.recv_timeout(wait) {
+                Ok((i, result)) => {
+                    results[i] = Some(result);
+                    pending -= 1;
+                },
+                Err(RecvTimeoutError::Timeout) => {},
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        // Keep any answer that came in just as time ran out.
+        while let Ok((i, result)) = rx.try_recv() {
+            results[i] = Some(result);
+        }
+
+        venues.iter().zip(results).map(|(venue, result)| {
+            let result = result.unwrap_or_else(|| {
+                Err(StockfighterError::Timeout(format!("heartbeating venue '{}'", venue)))
+            });
+            ((*venue).to_owned(), result)
+        }).collect()
     }
 }
 
 impl StockfighterAPI for StockfighterHttpApi {
     fn heartbeat(&self) -> StockfighterResult<()> {
-        let response = self.send_raw("/heartbeat").unwrap();
-        let json = response.as_object().unwrap();
-
-        let ok = json.get("ok").unwrap().as_boolean().unwrap();
-        if !ok {
-            return Err(json.get("error").unwrap().as_string().unwrap().to_owned());
-        }
+        self.send(&HEARTBEAT, "/heartbeat")?;
 
         Ok(())
     }
 
+    /// Entries without an id, a venue code or a known state are left out; see
+    /// `venues_with_skipped` to find out whether any were.
     fn venues(&self) -> StockfighterResult<Vec<VenueInfo>> {
-        let response = self.send_raw("/venues").unwrap();
-        let json = response.as_object().unwrap();
-
-        // This API call gives an `id` boolean field instead of an `ok` boolean field.
-        // I suspect this is a bug...
-        let ok = json.get("id").unwrap().as_boolean().unwrap();
-        if !ok {
-            return Err(json.get("error").unwrap().as_string().unwrap().to_owned());
-        }
-
-        let venues = json.get("venues").unwrap().as_array().unwrap().into_iter().map(|venue| {
-            let is_open = {
-                let state = venue.as_object().unwrap().get("state").unwrap().as_string().unwrap();
-                if state == "open" {
-                    true
-                } else if state == "closed" {
-                    false
-                } else {
-                    panic!(format!("Unexpected value for venue state: '{}'", state))
-                }
-            };
-
-            VenueInfo {
-                id: venue.as_object().unwrap().get("id").unwrap().as_u64().unwrap(),
-                name: venue.as_object().unwrap().get("name").unwrap().as_string().unwrap().to_owned(),
-                is_open: is_open,
-                venue: venue.as_object().unwrap().get("venue").unwrap().as_string().unwrap().to_owned(),
-            }
-        });
-
-        Ok(Vec::from_iter(venues))
+        self.venues_with_skipped().map(|(venues, _)| venues)
     }
 
     fn venue_heartbeat(&self, venue: &str) -> StockfighterResult<()> {
+        let venue = &*self.normalize_symbol(venue)?;
         let path = format!("/venues/{}/heartbeat", venue);
 
-        let response = self.send_raw(&*path).unwrap();
-        let json = response.as_object().unwrap();
+        let response = self.send(&VENUE_HEARTBEAT, &path)?;
 
-        let ok = json.get("ok").unwrap().as_boolean().unwrap();
-        if !ok {
-            return Err(json.get("error").unwrap().as_string().unwrap().to_owned());
+        if self.strict_venue_check {
+            match response.find("venue").and_then(|v| v.as_string()) {
+                Some(answered) if answered == venue => {},
+                Some(answered) => {
+                    return Err(StockfighterError::InvalidResponse(
+                        format!("heartbeat for venue {} was answered by venue {}", venue, answered)));
+                },
+                None => {
+                    return Err(StockfighterError::InvalidResponse(
+                        format!("heartbeat for venue {} didn't name a venue", venue)));
+                },
+            }
         }
 
         Ok(())
     }
 
     fn stock_orderbook(&self, venue: &str, stock: &str) -> StockfighterResult<Orderbook> {
+        let venue = self.normalize_symbol(venue)?;
+        let stock = self.normalize_symbol(stock)?;
         let path = format!("/venues/{}/stocks/{}", venue, stock);
 
-        let response = self.send_raw(&*path).unwrap();
-        let json = response.as_object().unwrap();
+        let response = self.send(&STOCK_ORDERBOOK, &path)?;
+        let mut book = parse_orderbook(&response)?;
 
-        let ok = json.get("ok").unwrap().as_boolean().unwrap();
-        if !ok {
-            return Err(json.get("error").unwrap().as_string().unwrap().to_owned());
+        if let Some(max_levels) = self.max_levels {
+            // The sort is stable, so orders within a level keep the priority the
+            // venue reported.
+            book.bids.sort_by(Order::bid_priority);
+            book.asks.sort_by(Order::ask_priority);
+            truncate_levels(&mut book.bids, max_levels);
+            truncate_levels(&mut book.asks, max_levels);
         }
 
-        let bids = json.get("bids").unwrap().as_array().unwrap().into_iter().map(|bid| {
-            Order {
-                price:  bid.as_object().unwrap().get("price").unwrap().as_u64().unwrap(),
-                qty:    bid.as_object().unwrap().get("qty").unwrap().as_u64().unwrap(),
-                is_buy: true,
+        Ok(book)
+    }
+
+    fn normalize_symbol(&self, code: &str) -> StockfighterResult<String> {
+        normalize_code(self.symbol_normalization, code)
+    }
+}
+
+// What `send` needs to know about a typed endpoint.
+struct Endpoint {
+    // The key its latency is recorded under.
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    name: &'static str,
+    // The boolean field it reports success in.
+    ok_field: &'static str,
+}
+
+const HEARTBEAT: Endpoint = Endpoint { name: "heartbeat", ok_field: "ok" };
+// This API call gives an `id` boolean field instead of an `ok` boolean field.
+// I suspect this is a bug...
+const VENUES: Endpoint = Endpoint { name: "venues", ok_field: "id" };
+const VENUE_HEARTBEAT: Endpoint = Endpoint { name: "venue_heartbeat", ok_field: "ok" };
+const STOCK_ORDERBOOK: Endpoint = Endpoint { name: "stock_orderbook", ok_field: "ok" };
+
+// Endpoints report success as e.g. `"ok": true`, and failure as `"ok": false`
+// plus an `"error"` message. Without the field, the HTTP status decides, so a
+// quirky or newly added endpoint doesn't fail just for leaving it out.
+fn check_ok(json: &Value, ok_field: &str, status: StatusCode) -> StockfighterResult<()> {
+    let ok = json.find(ok_field).and_then(|ok| ok.as_boolean())
+        .unwrap_or_else(|| status.is_success());
+    if ok {
+        return Ok(());
+    }
+
+    match json.find("error").and_then(|error| error.as_string()) {
+        Some(error) => Err(StockfighterError::Api(error.to_owned())),
+        None => Err(StockfighterError::Api(format!("Request failed without an error message (HTTP {})", status))),
+    }
+}
+
+struct RawResponse {
+    // As sent, after any interceptor rewrote it.
+    url: String,
+    status: StatusCode,
+    status_line: String,
+    content_type: Option<String>,
+    body: String,
+}
+
+// The API's wording when the X-Starfighter-Authorization key isn't recognized.
+fn is_auth_error(message: &str) -> bool {
+    message.contains("API key not found")
+}
+
+// A `venues` entry, or `None` if it lacks an id, a venue code or a known state.
+// A missing or null name falls back to the venue code.
+fn parse_venue(venue: &Value) -> Option<VenueInfo> {
+    let id = venue.find("id").and_then(|id| id.as_u64());
+    let code = venue.find("venue").and_then(|code| code.as_string());
+    let is_open = match venue.find("state").and_then(|state| state.as_string()) {
+        Some("open") => Some(true),
+        Some("closed") => Some(false),
+        _ => None,
+    };
+
+    match (id, code, is_open) {
+        (Some(id), Some(code), Some(is_open)) => {
+            let name = venue.find("name").and_then(|name| name.as_string()).unwrap_or(code);
+            Some(VenueInfo {
+                id,
+                name: name.to_owned(),
+                is_open,
+                venue: code.to_owned(),
+            })
+        },
+        _ => None,
+    }
+}
+
+// A `stock_orderbook` response, sides in the order the venue listed them.
+fn parse_orderbook(json: &Value) -> StockfighterResult<Orderbook> {
+    let bids = parse_side(json, "bids", true)?;
+    let asks = parse_side(json, "asks", false)?;
+
+    let timestamp = match json.find("ts").and_then(|ts| ts.as_string()) {
+        Some(ts) => match NaiveDateTime::parse_from_str(ts, "%+") {
+            Ok(timestamp) => timestamp,
+            Err(_) => return Err(StockfighterError::InvalidResponse(format!("unparseable orderbook 'ts' {:?}", ts))),
+        },
+        None => return Err(StockfighterError::InvalidResponse("no orderbook 'ts' string".to_owned())),
+    };
+
+    Ok(Orderbook {
+        bids,
+        asks,
+        timestamp,
+    })
+}
+
+// The API sends `null`, not `[]`, for a side without orders.
+fn parse_side(json: &Value, side: &str, is_buy: bool) -> StockfighterResult<Vec<Order>> {
+    match json.find(side) {
+        Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::Array(orders)) => orders.iter().map(|order| parse_order(order, is_buy)).collect(),
+        _ => Err(StockfighterError::InvalidResponse(format!("no orderbook '{}' array", side))),
+    }
+}
+
+fn parse_order(order: &Value, is_buy: bool) -> StockfighterResult<Order> {
+    let price = order.find("price").and_then(|price| price.as_u64());
+    let qty = order.find("qty").and_then(|qty| qty.as_u64());
+
+    match (price, qty) {
+        (Some(price), Some(qty)) => Ok(Order { price, qty, is_buy }),
+        _ => Err(StockfighterError::InvalidResponse(format!("order without a whole price and qty: {:?}", order))),
+    }
+}
+
+// `application/json`, or a structured `+json` type, ignoring parameters like charset.
+fn is_json_content_type(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+    essence == "application/json" || essence.ends_with("+json")
+}
+
+fn parse_body(body: &str) -> StockfighterResult<Value> {
+    match serde_json::from_str(body) {
+        Ok(json) => Ok(json),
+        Err(err) => {
+            Err(StockfighterError::Json {
+                error: err,
+                body_len: body.len(),
+                body_prefix: body_prefix(body).to_owned(),
+            })
+        },
+    }
+}
+
+fn normalize_code(mode: SymbolNormalization, code: &str) -> StockfighterResult<String> {
+    let normalized = code.trim().to_uppercase();
+    match mode {
+        SymbolNormalization::Off => Ok(code.to_owned()),
+        SymbolNormalization::Normalize => Ok(normalized),
+        SymbolNormalization::Strict if normalized == code => Ok(normalized),
+        SymbolNormalization::Strict => Err(StockfighterError::InvalidSymbol(code.to_owned())),
+    }
+}
+
+// The first few hundred characters of a response body, for error messages.
+fn body_prefix(body: &str) -> &str {
+    match body.char_indices().nth(200) {
+        Some((end, _)) => &body[..end],
+        None => body,
+    }
+}
+
+// Drop every order past the first `max_levels` distinct prices of a sorted side.
+fn truncate_levels(orders: &mut Vec<Order>, max_levels: usize) {
+    let mut levels = 0;
+    let mut last_price = None;
+    let mut cut = orders.len();
+
+    for (i, order) in orders.iter().enumerate() {
+        if last_price != Some(order.price) {
+            if levels == max_levels {
+                cut = i;
+                break;
             }
-        });
+            levels += 1;
+            last_price = Some(order.price);
+        }
+    }
+
+    orders.truncate(cut);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use mock_server::{CannedResponse, MockServer};
+    use super::*;
+
+    const HEARTBEAT_OK: &str = r#"{"ok": true, "error": ""}"#;
+
+    fn client(routes: Vec<(&str, CannedResponse)>) -> (MockServer, StockfighterHttpApi) {
+        let server = MockServer::start(routes);
+        let api = StockfighterHttpApi::new(server.base_url(), "secret");
+        (server, api)
+    }
+
+    // The simulator's venue, but listed as closed.
+    struct ClosedVenue(SimulatedVenue);
 
-        let asks = json.get("asks").unwrap().as_array().unwrap().into_iter().map(|ask| {
-            Order {
-                price:  ask.as_object().unwrap().get("price").unwrap().as_u64().unwrap(),
-                qty:    ask.as_object().unwrap().get("qty").unwrap().as_u64().unwrap(),
-                is_buy: false,
+    impl StockfighterAPI for ClosedVenue {
+        fn heartbeat(&self) -> StockfighterResult<()> {
+            self.0.heartbeat()
+        }
+
+        fn venues(&self) -> StockfighterResult<Vec<VenueInfo>> {
+            let mut venues = self.0.venues()?;
+            for venue in &mut venues {
+                venue.is_open = false;
             }
+            Ok(venues)
+        }
+
+        fn venue_heartbeat(&self, venue: &str) -> StockfighterResult<()> {
+            self.0.venue_heartbeat(venue)
+        }
+
+        fn stock_orderbook(&self, venue: &str, stock: &str) -> StockfighterResult<Orderbook> {
+            self.0.stock_orderbook(venue, stock)
+        }
+    }
+
+    fn two_sided_venue() -> SimulatedVenue {
+        let venue = SimulatedVenue::new("TESTEX");
+        venue.place_order("FOOBAR", true, 10, SimOrderType::Limit(5100));
+        venue.place_order("FOOBAR", false, 10, SimOrderType::Limit(5200));
+        venue
+    }
+
+    #[test]
+    fn a_two_sided_book_on_an_open_venue_is_tradeable() {
+        assert!(two_sided_venue().is_tradeable("TESTEX", "FOOBAR").unwrap());
+    }
+
+    #[test]
+    fn a_one_sided_book_is_not_tradeable() {
+        let venue = SimulatedVenue::new("TESTEX");
+        venue.place_order("FOOBAR", true, 10, SimOrderType::Limit(5100));
+
+        assert!(!venue.is_tradeable("TESTEX", "FOOBAR").unwrap());
+        assert!(!venue.is_tradeable("TESTEX", "EMPTY").unwrap());
+    }
+
+    #[test]
+    fn an_unlisted_venue_is_not_tradeable() {
+        assert!(!two_sided_venue().is_tradeable("OTHEREX", "FOOBAR").unwrap());
+    }
+
+    #[test]
+    fn a_closed_venue_is_not_tradeable() {
+        assert!(!ClosedVenue(two_sided_venue()).is_tradeable("TESTEX", "FOOBAR").unwrap());
+    }
+
+    #[test]
+    fn is_tradeable_normalizes_its_inputs() {
+        let (_server, mut api) = client(vec![
+            ("/venues", CannedResponse::json("200 OK", r#"{"ok": true, "venues": [{"name": "Test Exchange", "venue": "TESTEX", "id": 1, "state": "open"}]}"#)),
+            ("/venues/TESTEX/stocks/FOOBAR", CannedResponse::json("200 OK", r#"{"ok": true,
+                "bids": [{"price": 5100, "qty": 10, "isBuy": true}], "asks": [{"price": 5200, "qty": 10, "isBuy": false}],
+                "ts": "2015-12-04T09:02:16.680986205Z"}"#)),
+        ]);
+
+        assert!(api.is_tradeable(" testex", "foobar").unwrap());
+
+        api.symbol_normalization = SymbolNormalization::Strict;
+        match api.is_tradeable("testex", "FOOBAR") {
+            Err(StockfighterError::InvalidSymbol(ref code)) => assert_eq!(code, "testex"),
+            other => panic!("expected InvalidSymbol, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn heartbeat_succeeds_and_sends_the_api_key() {
+        let (server, api) = client(vec![("/heartbeat", CannedResponse::json("200 OK", HEARTBEAT_OK))]);
+
+        api.heartbeat().unwrap();
+        assert!(server.requests()[0].contains("X-Starfighter-Authorization: secret"));
+    }
+
+    #[test]
+    fn ok_false_is_an_api_error() {
+        let (_server, api) = client(vec![
+            ("/venues/NOPE/heartbeat", CannedResponse::json("404 Not Found", r#"{"ok": false, "error": "No venue exists with the symbol NOPE"}"#)),
+        ]);
+
+        match api.venue_heartbeat("NOPE") {
+            Err(StockfighterError::Api(ref message)) => assert_eq!(message, "No venue exists with the symbol NOPE"),
+            other => panic!("expected an Api error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unauthorized_keys_are_reported_as_such() {
+        let (_server, api) = client(vec![
+            ("/heartbeat", CannedResponse::json("401 Unauthorized", r#"{"ok": false, "error": "Auth/API key not found"}"#)),
+        ]);
+
+        match api.heartbeat() {
+            Err(StockfighterError::Unauthorized(ref message)) => assert!(message.contains("API key not found")),
+            other => panic!("expected Unauthorized, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn server_errors_report_the_status() {
+        let (_server, api) = client(vec![("/heartbeat", CannedResponse::html("503 Service Unavailable", "<html>bad gateway</html>"))]);
+
+        match api.heartbeat() {
+            Err(StockfighterError::ServerError { status: 503, ref message }) => {
+                assert_eq!(message.as_deref(), Some("<html>bad gateway</html>"));
+            },
+            other => panic!("expected a ServerError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn server_errors_keep_the_api_error_message() {
+        let (_server, api) = client(vec![
+            ("/heartbeat", CannedResponse::json("500 Internal Server Error", r#"{"ok": false, "error": "database is on fire"}"#)),
+            ("/venues/TESTEX/heartbeat", CannedResponse::html("502 Bad Gateway", "")),
+        ]);
+
+        match api.heartbeat() {
+            Err(StockfighterError::ServerError { status: 500, ref message }) => {
+                assert_eq!(message.as_deref(), Some("database is on fire"));
+            },
+            other => panic!("expected a ServerError, got {:?}", other),
+        }
+        match api.venue_heartbeat("TESTEX") {
+            Err(StockfighterError::ServerError { status: 502, message: None }) => {},
+            other => panic!("expected a ServerError without a message, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn non_json_content_types_are_rejected() {
+        let (_server, api) = client(vec![("/heartbeat", CannedResponse::html("200 OK", "<html>captive portal</html>"))]);
+
+        match api.heartbeat() {
+            Err(StockfighterError::UnexpectedContentType { ref content_type, ref body_prefix }) => {
+                assert_eq!(content_type, "text/html");
+                assert_eq!(body_prefix, "<html>captive portal</html>");
+            },
+            other => panic!("expected UnexpectedContentType, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn malformed_json_reports_what_arrived() {
+        let (_server, api) = client(vec![("/heartbeat", CannedResponse::json("200 OK", r#"{"ok": tr"#))]);
+
+        match api.heartbeat() {
+            Err(StockfighterError::Json { body_len: 9, ref body_prefix, .. }) => assert_eq!(body_prefix, r#"{"ok": tr"#),
+            other => panic!("expected a Json error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn oversized_responses_are_refused() {
+        let (_server, mut api) = client(vec![("/heartbeat", CannedResponse::json("200 OK", HEARTBEAT_OK))]);
+        api.max_response_bytes = 8;
+
+        match api.heartbeat() {
+            Err(StockfighterError::ResponseTooLarge(8)) => {},
+            other => panic!("expected ResponseTooLarge, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn an_unlimited_response_size_is_allowed() {
+        let (_server, mut api) = client(vec![("/heartbeat", CannedResponse::json("200 OK", HEARTBEAT_OK))]);
+        api.max_response_bytes = u64::MAX;
+
+        api.heartbeat().unwrap();
+    }
+
+    #[test]
+    fn malformed_venues_are_skipped_but_reported() {
+        let (_server, api) = client(vec![
+            ("/venues", CannedResponse::json("200 OK", r#"{"ok": true, "venues": [
+                {"name": "Test Exchange", "venue": "TESTEX", "id": 1, "state": "open"},
+                {"name": null, "venue": "MAINTEX", "id": 2, "state": "closed"},
+                {"name": "No Code", "id": 3, "state": "open"},
+                {"name": "Odd State", "venue": "ODDEX", "id": 4, "state": "halted"}]}"#)),
+        ]);
+
+        let (venues, skipped) = api.venues_with_skipped().unwrap();
+        let codes: Vec<_> = venues.iter().map(|venue| (&*venue.venue, &*venue.name, venue.is_open)).collect();
+        assert_eq!(codes, vec![("TESTEX", "Test Exchange", true), ("MAINTEX", "MAINTEX", false)]);
+        assert_eq!(skipped.len(), 2);
+        assert_eq!(skipped[1].find("venue").and_then(|venue| venue.as_string()), Some("ODDEX"));
+
+        assert_eq!(api.venues().unwrap().len(), 2);
+    }
+
+    struct Redirect;
+
+    impl Interceptor for Redirect {
+        fn on_request(&self, url: &mut String, headers: &mut Headers) {
+            *url = url.replace("/heartbeat", "/elsewhere");
+            headers.set_raw("X-Intercepted", vec![b"yes".to_vec()]);
+        }
+    }
+
+    struct Rewrite;
+
+    impl Interceptor for Rewrite {
+        fn on_response(&self, _status: StatusCode, body: &mut String) {
+            *body = body.replace("false", "true");
+        }
+    }
+
+    #[test]
+    fn interceptors_see_requests_and_responses() {
+        let (server, api) = client(vec![
+            ("/elsewhere", CannedResponse::json("200 OK", r#"{"ok": false, "error": "intercepted"}"#)),
+        ]);
+
+        let verbose = api.with_interceptor(Redirect).send_raw_verbose("/heartbeat").unwrap();
+        assert_eq!(verbose.request, format!("GET {}/elsewhere", server.base_url()));
+        assert!(server.requests()[0].contains("X-Intercepted: yes"));
+
+        match api.with_interceptor(Redirect).heartbeat() {
+            Err(StockfighterError::Api(ref message)) => assert_eq!(message, "intercepted"),
+            other => panic!("expected the redirected response, got {:?}", other),
+        }
+        api.with_interceptor(Redirect).with_interceptor(Rewrite).heartbeat().unwrap();
+
+        // The original client is untouched.
+        match api.heartbeat() {
+            Err(StockfighterError::Api(ref message)) => assert_eq!(message, "not found"),
+            other => panic!("expected a 404, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn the_pool_is_rebuilt_after_going_idle() {
+        let start = NaiveDateTime::from_timestamp(1449219736, 0);
+        let clock = MockClock::new(start);
+        let mut api = StockfighterHttpApi::new("http://127.0.0.1:1", "secret").with_clock(clock.clone());
+        api.pool_idle_timeout = Some(Duration::seconds(30));
+
+        let first = api.pooled_client();
+        clock.advance(Duration::seconds(30));
+        assert!(Arc::ptr_eq(&first, &api.pooled_client()), "rebuilt before the timeout");
+
+        // Each use resets the idle time.
+        clock.advance(Duration::seconds(30));
+        assert!(Arc::ptr_eq(&first, &api.pooled_client()), "rebuilt despite recent use");
+
+        clock.advance(Duration::seconds(31));
+        assert!(!Arc::ptr_eq(&first, &api.pooled_client()), "not rebuilt after going idle");
+    }
+
+    #[test]
+    fn timed_heartbeats_use_the_clients_clock() {
+        let (_server, api) = client(vec![("/heartbeat", CannedResponse::json("200 OK", HEARTBEAT_OK))]);
+        let api = api.with_clock(MockClock::new(NaiveDateTime::from_timestamp(1449219736, 0)));
+
+        assert_eq!(api.heartbeat_timed().unwrap(), Duration::zero());
+    }
+
+    fn eventually<F: Fn() -> bool>(condition: F) {
+        for _ in 0..1000 {
+            if condition() {
+                return;
+            }
+            thread::sleep(::std::time::Duration::from_millis(5));
+        }
+        panic!("condition not met within 5s");
+    }
+
+    #[test]
+    fn venue_heartbeat_all_keeps_input_order_and_times_out_slow_venues() {
+        let (server, api) = client(vec![
+            ("/venues/FASTEX/heartbeat", CannedResponse::json("200 OK", r#"{"ok": true, "venue": "FASTEX"}"#)),
+            ("/venues/SLOWEX/heartbeat", CannedResponse::json("200 OK", r#"{"ok": true, "venue": "SLOWEX"}"#)
+                .delayed(::std::time::Duration::from_secs(10))),
+        ]);
+        let clock = MockClock::new(NaiveDateTime::from_timestamp(1449219736, 0));
+        let api = api.with_clock(clock.clone());
+
+        // Run out the clock once every request is in and the quick ones have had
+        // ample time to come back.
+        let timekeeper = thread::spawn(move || {
+            eventually(|| server.requests().len() == 3);
+            thread::sleep(::std::time::Duration::from_millis(200));
+            clock.advance(Duration::seconds(5));
         });
 
-        let timestamp = NaiveDateTime::parse_from_str(
-            json.get("ts").unwrap().as_string().unwrap(),
-            "%+"
-        ).unwrap();
+        let results = api.venue_heartbeat_all(&["SLOWEX", "FASTEX", "NOPE"], Duration::seconds(5));
+        timekeeper.join().unwrap();
 
-        Ok(Orderbook {
-            bids: Vec::from_iter(bids),
-            asks: Vec::from_iter(asks),
-            timestamp: timestamp,
-        })
+        let venues: Vec<_> = results.iter().map(|(venue, _)| venue.as_str()).collect();
+        assert_eq!(venues, vec!["SLOWEX", "FASTEX", "NOPE"]);
+        match results[0].1 {
+            Err(StockfighterError::Timeout(ref message)) => assert!(message.contains("SLOWEX")),
+            ref other => panic!("expected a Timeout, got {:?}", other),
+        }
+        assert!(results[1].1.is_ok());
+        match results[2].1 {
+            Err(StockfighterError::Api(ref message)) => assert_eq!(message, "not found"),
+            ref other => panic!("expected an Api error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn closures_can_be_registered_as_hooks() {
+        let (server, api) = client(vec![
+            ("/elsewhere", CannedResponse::json("200 OK", r#"{"ok": false, "error": "intercepted"}"#)),
+        ]);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+
+        let log = seen.clone();
+        let api = api
+            .with_request_hook(|url, headers| {
+                *url = url.replace("/heartbeat", "/elsewhere");
+                headers.set_raw("X-Intercepted", vec![b"yes".to_vec()]);
+            })
+            .with_response_hook(move |status, body| {
+                log.lock().unwrap().push(status);
+                *body = body.replace("false", "true");
+            });
+
+        api.heartbeat().unwrap();
+        assert!(server.requests()[0].contains("X-Intercepted: yes"));
+        assert_eq!(*seen.lock().unwrap(), vec![StatusCode::Ok]);
+    }
+
+    #[test]
+    fn stock_orderbook_parses_both_sides() {
+        let (_server, api) = client(vec![
+            ("/venues/TESTEX/stocks/FOOBAR", CannedResponse::json("200 OK", r#"{"ok": true, "venue": "TESTEX", "symbol": "FOOBAR",
+                "bids": [{"price": 5200, "qty": 10, "isBuy": true}, {"price": 5100, "qty": 5, "isBuy": true}],
+                "asks": [{"price": 5300, "qty": 3, "isBuy": false}],
+                "ts": "2015-12-04T09:02:16.680986205Z"}"#)),
+        ]);
+
+        let book = api.stock_orderbook("TESTEX", "FOOBAR").unwrap();
+        assert_eq!(book.bids, vec![Order { price: 5200, qty: 10, is_buy: true }, Order { price: 5100, qty: 5, is_buy: true }]);
+        assert_eq!(book.asks, vec![Order { price: 5300, qty: 3, is_buy: false }]);
+        assert_eq!(book.timestamp.timestamp_subsec_nanos(), 680986205);
+    }
+
+    fn orderbook_for(body: &str) -> StockfighterResult<Orderbook> {
+        let (_server, api) = client(vec![("/venues/TESTEX/stocks/FOOBAR", CannedResponse::json("200 OK", body))]);
+        api.stock_orderbook("TESTEX", "FOOBAR")
+    }
+
+    #[test]
+    fn a_null_side_is_empty() {
+        let book = orderbook_for(r#"{"ok": true, "bids": null, "asks": [{"price": 5300, "qty": 3, "isBuy": false}],
+            "ts": "2015-12-04T09:02:16.680986205Z"}"#).unwrap();
+
+        assert!(book.bids.is_empty());
+        assert_eq!(book.asks.len(), 1);
+    }
+
+    #[test]
+    fn malformed_orderbooks_are_invalid_responses() {
+        let ts = r#""ts": "2015-12-04T09:02:16.680986205Z""#;
+        let order = r#"{"price": 5100, "qty": 10}"#;
+        let cases = vec![
+            (format!(r#"{{"ok": true, "asks": [], {}}}"#, ts), "no orderbook 'bids' array"),
+            (format!(r#"{{"ok": true, "bids": [], "asks": {{}}, {}}}"#, ts), "no orderbook 'asks' array"),
+            (format!(r#"{{"ok": true, "bids": "none", "asks": [], {}}}"#, ts), "no orderbook 'bids' array"),
+            (format!(r#"{{"ok": true, "bids": [{{"qty": 10}}], "asks": [], {}}}"#, ts), "order without a whole price and qty"),
+            (format!(r#"{{"ok": true, "bids": [], "asks": [{{"price": 5100, "qty": "10"}}], {}}}"#, ts), "order without a whole price and qty"),
+            (format!(r#"{{"ok": true, "bids": [{{"price": -5100, "qty": 10}}], "asks": [], {}}}"#, ts), "order without a whole price and qty"),
+            (format!(r#"{{"ok": true, "bids": [{}], "asks": []}}"#, order), "no orderbook 'ts' string"),
+            (format!(r#"{{"ok": true, "bids": [{}], "asks": [], "ts": 1449219736}}"#, order), "no orderbook 'ts' string"),
+            (format!(r#"{{"ok": true, "bids": [{}], "asks": [], "ts": "yesterday"}}"#, order), "unparseable orderbook 'ts'"),
+            (r#"{"ok": true}"#.to_owned(), "no orderbook 'bids' array"),
+        ];
+
+        for (body, expected) in cases {
+            match orderbook_for(&body) {
+                Err(StockfighterError::InvalidResponse(ref message)) => {
+                    assert!(message.starts_with(expected), "{}: got {}", body, message);
+                },
+                other => panic!("{}: expected InvalidResponse, got {:?}", body, other),
+            }
+        }
     }
 }
@@ -0,0 +1,73 @@
+//! Per-endpoint request latency, recorded when the `metrics` feature is enabled.
+
+use chrono::{Duration};
+use std::collections::{HashMap};
+
+
+/// Upper bounds, in milliseconds, of the latency histogram buckets. Anything
+/// slower lands in one final overflow bucket.
+pub const BUCKET_BOUNDS_MS: [i64; 12] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000, 2000, 5000];
+
+#[derive(Clone, Debug)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub total: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    /// Request counts per bucket of `BUCKET_BOUNDS_MS`, plus the overflow bucket.
+    pub buckets: Vec<u64>,
+}
+
+impl LatencySummary {
+    fn new() -> LatencySummary {
+        LatencySummary {
+            count: 0,
+            total: Duration::zero(),
+            min: Duration::max_value(),
+            max: Duration::zero(),
+            buckets: vec![0; BUCKET_BOUNDS_MS.len() + 1],
+        }
+    }
+
+    fn record(&mut self, latency: Duration) {
+        self.count += 1;
+        self.total = self.total + latency;
+        if latency < self.min {
+            self.min = latency;
+        }
+        if latency > self.max {
+            self.max = latency;
+        }
+
+        let ms = latency.num_milliseconds();
+        let bucket = BUCKET_BOUNDS_MS.iter().position(|&bound| ms <= bound).unwrap_or(BUCKET_BOUNDS_MS.len());
+        self.buckets[bucket] += 1;
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        self.total.num_nanoseconds().map(|nanos| Duration::nanoseconds(nanos / self.count as i64))
+    }
+}
+
+
+/// Latency summaries keyed by endpoint name (`"heartbeat"`, `"stock_orderbook"`, ...).
+#[derive(Clone, Debug, Default)]
+pub struct EndpointMetrics {
+    pub endpoints: HashMap<String, LatencySummary>,
+}
+
+impl EndpointMetrics {
+    pub fn record(&mut self, endpoint: &str, latency: Duration) {
+        self.endpoints
+            .entry(endpoint.to_owned())
+            .or_insert_with(LatencySummary::new)
+            .record(latency);
+    }
+
+    pub fn get(&self, endpoint: &str) -> Option<&LatencySummary> {
+        self.endpoints.get(endpoint)
+    }
+}
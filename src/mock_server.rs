@@ -0,0 +1,102 @@
+//! A tiny HTTP server returning canned responses, for testing the client's
+//! transport handling without the real API.
+
+use std::collections::{HashMap};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration};
+
+
+#[derive(Clone, Debug)]
+pub struct CannedResponse {
+    pub status: &'static str,
+    pub content_type: Option<&'static str>,
+    pub body: String,
+    /// How long to stall before answering.
+    pub delay: Option<Duration>,
+}
+
+impl CannedResponse {
+    pub fn json(status: &'static str, body: &str) -> CannedResponse {
+        CannedResponse { status, content_type: Some("application/json"), body: body.to_owned(), delay: None }
+    }
+
+    pub fn html(status: &'static str, body: &str) -> CannedResponse {
+        CannedResponse { status, content_type: Some("text/html"), body: body.to_owned(), delay: None }
+    }
+
+    pub fn delayed(self, delay: Duration) -> CannedResponse {
+        CannedResponse { delay: Some(delay), ..self }
+    }
+}
+
+/// Serves its routes on an ephemeral localhost port until the test process
+/// exits, each connection on a thread of its own. Unknown paths get the API's
+/// 404 (`"ok": false`).
+pub struct MockServer {
+    base_url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockServer {
+    pub fn start(routes: Vec<(&str, CannedResponse)>) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let routes: Arc<HashMap<String, CannedResponse>> =
+            Arc::new(routes.into_iter().map(|(path, response)| (path.to_owned(), response)).collect());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let log = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let routes = routes.clone();
+                let log = log.clone();
+                thread::spawn(move || serve(stream, &routes, &log));
+            }
+        });
+
+        MockServer { base_url, requests }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// The raw head (request line and headers) of every request served so far.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+
+fn serve(mut stream: TcpStream, routes: &HashMap<String, CannedResponse>, log: &Mutex<Vec<String>>) {
+    // Body-less GETs only, so the request ends with its headers.
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut byte) {
+            Ok(1) => head.push(byte[0]),
+            _ => return,
+        }
+    }
+    let head = String::from_utf8_lossy(&head).into_owned();
+    let path = head.split_whitespace().nth(1).unwrap_or("").to_owned();
+    log.lock().unwrap().push(head);
+
+    let not_found = CannedResponse::json("404 Not Found", r#"{"ok": false, "error": "not found"}"#);
+    let response = routes.get(&path).unwrap_or(&not_found);
+    if let Some(delay) = response.delay {
+        thread::sleep(delay);
+    }
+
+    let mut reply = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+                            response.status, response.body.len());
+    if let Some(content_type) = response.content_type {
+        reply.push_str(&format!("Content-Type: {}\r\n", content_type));
+    }
+    reply.push_str("\r\n");
+    reply.push_str(&response.body);
+    let _ = stream.write_all(reply.as_bytes());
+}
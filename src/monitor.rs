@@ -0,0 +1,280 @@
+use chrono::{Duration};
+use chrono::naive::datetime::{NaiveDateTime};
+use std::cmp;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use {Clock, StockfighterAPI, StockfighterError, StockfighterResult};
+
+
+#[derive(Copy, Clone, Debug)]
+struct HeartbeatState {
+    is_healthy: bool,
+    last_ok: Option<NaiveDateTime>,
+}
+
+/// Handle to a background thread that periodically heartbeats the API and a venue.
+#[derive(Debug)]
+pub struct HeartbeatMonitor {
+    state: Arc<Mutex<HeartbeatState>>,
+    stop: Arc<StopSignal>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug, Default)]
+struct StopSignal {
+    stopped: Mutex<bool>,
+    wake: Condvar,
+}
+
+impl StopSignal {
+    fn stop(&self) {
+        *self.stopped.lock().unwrap() = true;
+        self.wake.notify_all();
+    }
+}
+
+impl HeartbeatMonitor {
+    /// Heartbeats once straight away, then again whenever `clock` has moved on by
+    /// `interval`. The interval is measured on `clock` but waited out in real time,
+    /// re-reading the clock at least every 100ms; so with a `MockClock`, the next
+    /// round starts shortly after the test advances it far enough.
+    pub fn start<A, C>(api: A, venue: &str, interval: Duration, clock: C) -> HeartbeatMonitor
+        where A: StockfighterAPI + Send + 'static, C: Clock + 'static
+    {
+        let state = Arc::new(Mutex::new(HeartbeatState { is_healthy: false, last_ok: None }));
+        let stop = Arc::new(StopSignal::default());
+
+        let thread = {
+            let state = state.clone();
+            let stop = stop.clone();
+            let venue = venue.to_owned();
+
+            thread::spawn(move || {
+                let poll = Duration::milliseconds(100);
+
+                loop {
+                    let is_healthy = api.heartbeat().is_ok() && api.venue_heartbeat(&venue).is_ok();
+                    {
+                        let mut state = state.lock().unwrap();
+                        state.is_healthy = is_healthy;
+                        if is_healthy {
+                            state.last_ok = Some(clock.now());
+                        }
+                    }
+
+                    // `stop()` wakes us early rather than leaving it to wait out the interval.
+                    let next = clock.now() + interval;
+                    let mut stopped = stop.stopped.lock().unwrap();
+                    loop {
+                        let now = clock.now();
+                        if *stopped || now >= next {
+                            break;
+                        }
+                        let wait = cmp::min(next - now, poll).to_std().unwrap();
+                        stopped = stop.wake.wait_timeout(stopped, wait).unwrap().0;
+                    }
+                    if *stopped {
+                        break;
+                    }
+                }
+            })
+        };
+
+        HeartbeatMonitor {
+            state,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Whether both the API and the venue answered the most recent round of heartbeats.
+    pub fn is_healthy(&self) -> bool {
+        self.state.lock().unwrap().is_healthy
+    }
+
+    /// When a round of heartbeats last succeeded, if ever.
+    pub fn last_ok(&self) -> Option<NaiveDateTime> {
+        self.state.lock().unwrap().last_ok
+    }
+
+    /// Stops the thread and waits for it to finish, which it does as soon as any
+    /// heartbeat in flight returns.
+    pub fn stop(mut self) {
+        self.stop.stop();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for HeartbeatMonitor {
+    fn drop(&mut self) {
+        self.stop.stop();
+    }
+}
+
+
+/// Heartbeats the API and `venue` until both answer, backing off exponentially
+/// (from 100ms, capped at 5s) between attempts. Gives up with a `Timeout` error,
+/// carrying the last failure, once `timeout` has gone by.
+pub fn wait_until_ready<A, C>(api: &A, venue: &str, timeout: Duration, clock: &C) -> StockfighterResult<()>
+    where A: StockfighterAPI, C: Clock
+{
+    let deadline = clock.now() + timeout;
+    let max_delay = Duration::seconds(5);
+    let mut delay = Duration::milliseconds(100);
+
+    loop {
+        let error = match api.heartbeat().and_then(|_| api.venue_heartbeat(venue)) {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+
+        let now = clock.now();
+        if now >= deadline {
+            return Err(StockfighterError::Timeout(
+                format!("{} not ready after {}ms; last error: {}", venue, timeout.num_milliseconds(), error)));
+        }
+
+        clock.sleep(cmp::min(delay, deadline - now));
+        delay = cmp::min(delay * 2, max_delay);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration};
+    use chrono::naive::datetime::{NaiveDateTime};
+    use std::sync::{Arc};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::thread;
+    use std::time;
+
+    use super::*;
+    use {MockClock, Orderbook, StockfighterAPI, StockfighterError, StockfighterResult, VenueInfo};
+
+    // Answers heartbeats for TESTEX while `healthy` is set, counting the rounds.
+    #[derive(Clone, Default)]
+    struct Switchable {
+        healthy: Arc<AtomicBool>,
+        rounds: Arc<AtomicUsize>,
+    }
+
+    impl StockfighterAPI for Switchable {
+        fn heartbeat(&self) -> StockfighterResult<()> {
+            self.rounds.fetch_add(1, Ordering::SeqCst);
+            if self.healthy.load(Ordering::SeqCst) {
+                Ok(())
+            } else {
+                Err(StockfighterError::Api("down".to_owned()))
+            }
+        }
+
+        fn venues(&self) -> StockfighterResult<Vec<VenueInfo>> {
+            Err(StockfighterError::Api("not simulated".to_owned()))
+        }
+
+        fn venue_heartbeat(&self, venue: &str) -> StockfighterResult<()> {
+            match venue {
+                "TESTEX" => Ok(()),
+                _ => Err(StockfighterError::Api(format!("No venue exists with the symbol {}", venue))),
+            }
+        }
+
+        fn stock_orderbook(&self, _venue: &str, _stock: &str) -> StockfighterResult<Orderbook> {
+            Err(StockfighterError::Api("not simulated".to_owned()))
+        }
+    }
+
+    fn eventually<F: Fn() -> bool>(condition: F) {
+        for _ in 0..1000 {
+            if condition() {
+                return;
+            }
+            thread::sleep(time::Duration::from_millis(5));
+        }
+        panic!("condition not met within 5s");
+    }
+
+    fn start() -> NaiveDateTime {
+        NaiveDateTime::from_timestamp(1449219736, 0)
+    }
+
+    #[test]
+    fn the_monitor_heartbeats_once_per_interval_of_its_clock() {
+        let api = Switchable::default();
+        api.healthy.store(true, Ordering::SeqCst);
+        let clock = MockClock::new(start());
+        let interval = Duration::seconds(10);
+
+        let monitor = HeartbeatMonitor::start(api.clone(), "TESTEX", interval, clock.clone());
+        eventually(|| monitor.is_healthy());
+        assert_eq!(monitor.last_ok(), Some(start()));
+
+        // With the clock standing still, so does the monitor.
+        thread::sleep(time::Duration::from_millis(250));
+        assert_eq!(api.rounds.load(Ordering::SeqCst), 1);
+
+        api.healthy.store(false, Ordering::SeqCst);
+        clock.advance(interval);
+        eventually(|| !monitor.is_healthy());
+        assert_eq!(monitor.last_ok(), Some(start()));
+
+        api.healthy.store(true, Ordering::SeqCst);
+        clock.advance(interval);
+        eventually(|| monitor.is_healthy());
+        assert_eq!(monitor.last_ok(), Some(start() + interval * 2));
+        assert_eq!(api.rounds.load(Ordering::SeqCst), 3);
+
+        monitor.stop();
+    }
+
+    #[test]
+    fn an_unknown_venue_is_never_healthy() {
+        let api = Switchable::default();
+        api.healthy.store(true, Ordering::SeqCst);
+
+        let monitor = HeartbeatMonitor::start(api.clone(), "NOPE", Duration::seconds(10), MockClock::new(start()));
+        eventually(|| api.rounds.load(Ordering::SeqCst) == 1);
+        assert!(!monitor.is_healthy());
+        assert_eq!(monitor.last_ok(), None);
+
+        monitor.stop();
+    }
+
+    #[test]
+    fn stop_joins_the_thread_without_waiting_out_the_interval() {
+        let api = Switchable::default();
+        let clock = MockClock::new(start());
+
+        let monitor = HeartbeatMonitor::start(api.clone(), "TESTEX", Duration::hours(1), clock.clone());
+        eventually(|| api.rounds.load(Ordering::SeqCst) == 1);
+
+        let stopping = time::Instant::now();
+        monitor.stop();
+        assert!(stopping.elapsed() < time::Duration::from_secs(1));
+
+        clock.advance(Duration::hours(2));
+        thread::sleep(time::Duration::from_millis(250));
+        assert_eq!(api.rounds.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn wait_until_ready_backs_off_until_the_timeout() {
+        let api = Switchable::default();
+        let clock = MockClock::new(start());
+
+        match wait_until_ready(&api, "TESTEX", Duration::seconds(1), &clock) {
+            Err(StockfighterError::Timeout(ref message)) => assert!(message.contains("last error: down")),
+            other => panic!("expected a Timeout, got {:?}", other),
+        }
+        // Sleeps of 100, 200 and 400ms, then the remaining 300ms.
+        assert_eq!(api.rounds.load(Ordering::SeqCst), 5);
+        assert_eq!(clock.now(), start() + Duration::seconds(1));
+
+        api.healthy.store(true, Ordering::SeqCst);
+        wait_until_ready(&api, "TESTEX", Duration::seconds(1), &clock).unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+use chrono::{Duration};
+use chrono::naive::datetime::{NaiveDateTime};
+
+use {Order, Orderbook};
+
+
+/// Estimates how much quantity is queued ahead of a resting order at one price
+/// level, and how quickly that queue is draining, from successive snapshots.
+///
+/// The public book doesn't say which shares at a level are ahead of us, so this
+/// assumes the worst: the whole level is ahead when tracking starts, any later
+/// growth joined behind us, and any shrinkage (fills or cancels) came from in
+/// front of us.
+#[derive(Clone, Debug)]
+pub struct QueueEstimator {
+    price: u64,
+    is_buy: bool,
+    qty_ahead: Option<u64>,
+    first_seen: Option<NaiveDateTime>,
+    last_seen: Option<(NaiveDateTime, u64)>,
+    depleted: u64,
+}
+
+impl QueueEstimator {
+    pub fn new(price: u64, is_buy: bool) -> QueueEstimator {
+        QueueEstimator {
+            price,
+            is_buy,
+            qty_ahead: None,
+            first_seen: None,
+            last_seen: None,
+            depleted: 0,
+        }
+    }
+
+    /// Tracks from a known queue position instead of the first snapshot's level size.
+    pub fn with_qty_ahead(price: u64, is_buy: bool, qty_ahead: u64) -> QueueEstimator {
+        QueueEstimator { qty_ahead: Some(qty_ahead), ..QueueEstimator::new(price, is_buy) }
+    }
+
+    /// Feeds the next snapshot. Snapshots no newer than the last one seen are ignored.
+    pub fn ingest(&mut self, book: &Orderbook) {
+        let level_qty = level_qty(book, self.price, self.is_buy);
+
+        match self.last_seen {
+            None => {
+                self.first_seen = Some(book.timestamp);
+                if self.qty_ahead.is_none() {
+                    self.qty_ahead = Some(level_qty);
+                }
+            },
+            Some((last_ts, last_qty)) => {
+                if book.timestamp <= last_ts {
+                    return;
+                }
+
+                if level_qty < last_qty {
+                    let decrease = last_qty - level_qty;
+                    self.depleted += decrease;
+                    self.qty_ahead = self.qty_ahead.map(|ahead| ahead.saturating_sub(decrease));
+                }
+            },
+        }
+
+        self.last_seen = Some((book.timestamp, level_qty));
+    }
+
+    pub fn qty_ahead(&self) -> Option<u64> {
+        self.qty_ahead
+    }
+
+    /// Average rate, in shares per second, at which the level has been draining.
+    pub fn depletion_rate(&self) -> Option<f64> {
+        let (first, (last, _)) = match (self.first_seen, self.last_seen) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return None,
+        };
+
+        let elapsed_ms = (last - first).num_milliseconds();
+        if elapsed_ms <= 0 {
+            return None;
+        }
+
+        Some(self.depleted as f64 * 1000.0 / elapsed_ms as f64)
+    }
+
+    /// Time until the queue ahead of us drains at the observed rate. `None` if the
+    /// level hasn't been seen draining yet.
+    pub fn estimated_time_to_fill(&self) -> Option<Duration> {
+        let ahead = self.qty_ahead?;
+        if ahead == 0 {
+            return Some(Duration::zero());
+        }
+
+        match self.depletion_rate() {
+            Some(rate) if rate > 0.0 => {
+                Some(Duration::milliseconds((ahead as f64 / rate * 1000.0) as i64))
+            },
+            _ => None,
+        }
+    }
+}
+
+
+/// Where one of my resting orders sits in line at its price level, as far as the
+/// public book can tell.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct QueueRank {
+    /// My 0-based position among the orders at the level, if it can be told.
+    pub rank: Option<usize>,
+    /// Shares queued in front of me; known exactly when `rank` is.
+    pub qty_ahead: Option<u64>,
+    pub orders_at_level: usize,
+}
+
+impl QueueRank {
+    pub fn is_alone(&self) -> bool {
+        self.orders_at_level == 1
+    }
+
+    pub fn is_first(&self) -> bool {
+        self.rank == Some(0)
+    }
+}
+
+/// Finds `mine` (e.g. one of my open orders from the venue) in `book`. `None` if
+/// nothing rests at its price on its side.
+///
+/// The public book lists a level's orders in arrival order, but anonymously: an
+/// entry is only taken to be mine if it's the one entry at that price with my
+/// order's open quantity. Otherwise the rank is unknown, though `orders_at_level`
+/// still says whether I'm alone there. A book truncated by `max_levels` is fine,
+/// but one merged by `to_l2` has lost the per-order detail this relies on.
+pub fn queue_rank(book: &Orderbook, mine: &Order) -> Option<QueueRank> {
+    let side = if mine.is_buy { &book.bids } else { &book.asks };
+    let level: Vec<&Order> = side.iter().filter(|order| order.price == mine.price).collect();
+    if level.is_empty() {
+        return None;
+    }
+
+    let mut matches = level.iter().enumerate().filter(|&(_, order)| order.qty == mine.qty);
+    let rank = match (matches.next(), matches.next()) {
+        (Some((i, _)), None) => Some(i),
+        _ => None,
+    };
+
+    Some(QueueRank {
+        rank,
+        qty_ahead: rank.map(|i| level[..i].iter().map(|order| order.qty).sum()),
+        orders_at_level: level.len(),
+    })
+}
+
+
+fn level_qty(book: &Orderbook, price: u64, is_buy: bool) -> u64 {
+    let side = if is_buy { &book.bids } else { &book.asks };
+    side.iter().filter(|order| order.price == price).map(|order| order.qty).sum()
+}
@@ -0,0 +1,283 @@
+//! A compact binary format for capturing a stream of orderbook snapshots.
+//!
+//! The layout is a 4-byte magic and a version byte, followed by one record per
+//! snapshot. Every integer is little-endian:
+//!
+//! ```text
+//! record := 0x01 ts_secs:i64 ts_nanos:u32 bids:side asks:side
+//! side   := count:u32 (price:u64 qty:u64){count}
+//! ```
+
+use std::io::{self, Read, Write};
+use std::slice;
+use chrono::naive::datetime::{NaiveDateTime};
+
+use {Order, Orderbook};
+
+
+const MAGIC: &[u8; 4] = b"SFOB";
+const VERSION: u8 = 1;
+const RECORD_TAG: u8 = 1;
+
+
+pub fn write_snapshots<'a, W, I>(writer: &mut W, snapshots: I) -> io::Result<()>
+    where W: Write, I: IntoIterator<Item=&'a Orderbook>
+{
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION])?;
+
+    for book in snapshots {
+        writer.write_all(&[RECORD_TAG])?;
+        write_u64(writer, book.timestamp.timestamp() as u64)?;
+        write_u32(writer, book.timestamp.timestamp_subsec_nanos())?;
+        write_side(writer, &book.bids)?;
+        write_side(writer, &book.asks)?;
+    }
+
+    writer.flush()
+}
+
+pub fn read_snapshots<R: Read>(reader: &mut R) -> io::Result<Vec<Orderbook>> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("Not an orderbook recording"));
+    }
+
+    let mut version = [0u8; 1];
+    reader.read_exact(&mut version)?;
+    if version[0] != VERSION {
+        return Err(invalid_data("Unsupported orderbook recording version"));
+    }
+
+    let mut snapshots = Vec::new();
+    while let Some(tag) = read_tag(reader)? {
+        if tag != RECORD_TAG {
+            return Err(invalid_data("Corrupt orderbook record"));
+        }
+
+        let secs = read_u64(reader)? as i64;
+        let nanos = read_u32(reader)?;
+        let timestamp = match NaiveDateTime::from_timestamp_opt(secs, nanos) {
+            Some(timestamp) => timestamp,
+            None => return Err(invalid_data("Invalid orderbook timestamp")),
+        };
+
+        let bids = read_side(reader, true)?;
+        let asks = read_side(reader, false)?;
+
+        snapshots.push(Orderbook {
+            bids,
+            asks,
+            timestamp,
+        });
+    }
+
+    Ok(snapshots)
+}
+
+
+/// A recording loaded into memory.
+#[derive(Clone, Debug)]
+pub struct Recording {
+    pub snapshots: Vec<Orderbook>,
+}
+
+impl Recording {
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Recording> {
+        Ok(Recording { snapshots: read_snapshots(reader)? })
+    }
+
+    /// Snapshots timestamped in `[start, end)`, in recorded order.
+    pub fn snapshots_between<'a>(&'a self, start: NaiveDateTime, end: NaiveDateTime) -> SnapshotsBetween<'a> {
+        SnapshotsBetween {
+            iter: self.snapshots.iter(),
+            start,
+            end,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SnapshotsBetween<'a> {
+    iter: slice::Iter<'a, Orderbook>,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+}
+
+impl<'a> Iterator for SnapshotsBetween<'a> {
+    type Item = &'a Orderbook;
+
+    fn next(&mut self) -> Option<&'a Orderbook> {
+        let (start, end) = (self.start, self.end);
+        self.iter.by_ref().find(|book| start <= book.timestamp && book.timestamp < end)
+    }
+}
+
+
+fn write_side<W: Write>(writer: &mut W, orders: &[Order]) -> io::Result<()> {
+    if orders.len() as u64 > u64::from(u32::MAX) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Too many orders on one side to record"));
+    }
+    write_u32(writer, orders.len() as u32)?;
+    for order in orders {
+        write_u64(writer, order.price)?;
+        write_u64(writer, order.qty)?;
+    }
+    Ok(())
+}
+
+fn read_side<R: Read>(reader: &mut R, is_buy: bool) -> io::Result<Vec<Order>> {
+    let count = read_u32(reader)?;
+
+    let mut orders = Vec::new();
+    for _ in 0..count {
+        let price = read_u64(reader)?;
+        let qty = read_u64(reader)?;
+        orders.push(Order { price, qty, is_buy });
+    }
+    Ok(orders)
+}
+
+// The next record's tag, or `None` at a clean end of input.
+fn read_tag<R: Read>(reader: &mut R) -> io::Result<Option<u8>> {
+    let mut tag = [0u8; 1];
+    loop {
+        match reader.read(&mut tag) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(tag[0])),
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {},
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::naive::datetime::{NaiveDateTime};
+    use std::io::{self, Cursor, Read};
+
+    use super::*;
+    use {Orderbook};
+
+    fn book(secs: i64, nanos: u32) -> Orderbook {
+        Orderbook::builder()
+            .bid(5100, 20)
+            .bid(5050, 3)
+            .ask(5200, 10)
+            .timestamp(NaiveDateTime::from_timestamp(secs, nanos))
+            .build()
+    }
+
+    fn record(snapshots: &[Orderbook]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_snapshots(&mut buf, snapshots).unwrap();
+        buf
+    }
+
+    #[test]
+    fn round_trips_snapshots() {
+        let one_sided = Orderbook::builder()
+            .ask(5200, 10)
+            .timestamp(NaiveDateTime::from_timestamp(1449219736, 1))
+            .build();
+        let empty = Orderbook::builder()
+            .timestamp(NaiveDateTime::from_timestamp(1449219737, 0))
+            .build();
+        let snapshots = vec![book(1449219736, 680986205), one_sided, empty];
+
+        let read = read_snapshots(&mut Cursor::new(record(&snapshots))).unwrap();
+
+        assert_eq!(read, snapshots);
+        // `PartialEq` ignores the order within a side; the format shouldn't lose it.
+        for (read, written) in read.iter().zip(&snapshots) {
+            assert_eq!(read.bids, written.bids);
+            assert_eq!(read.asks, written.asks);
+        }
+        assert_eq!(read[0].timestamp.timestamp_subsec_nanos(), 680986205);
+    }
+
+    #[test]
+    fn round_trips_an_empty_recording() {
+        assert!(read_snapshots(&mut Cursor::new(record(&[]))).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut buf = record(&[book(1449219736, 0)]);
+        buf[0] = b'X';
+
+        let err = read_snapshots(&mut Cursor::new(buf)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_a_truncated_record() {
+        let mut buf = record(&[book(1449219736, 0), book(1449219737, 0)]);
+        let len = buf.len();
+        buf.truncate(len - 3);
+
+        let err = read_snapshots(&mut Cursor::new(buf)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    // Fails every other read with `Interrupted`, as a read cut short by a signal would.
+    struct Interrupting<R> {
+        inner: R,
+        interrupt: bool,
+    }
+
+    impl<R: Read> Read for Interrupting<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                Err(io::Error::new(io::ErrorKind::Interrupted, "interrupted"))
+            } else {
+                self.inner.read(buf)
+            }
+        }
+    }
+
+    #[test]
+    fn retries_interrupted_reads() {
+        let snapshots = vec![book(1449219736, 0), book(1449219737, 0)];
+        let mut reader = Interrupting { inner: Cursor::new(record(&snapshots)), interrupt: false };
+
+        assert_eq!(read_snapshots(&mut reader).unwrap(), snapshots);
+    }
+
+    #[test]
+    fn filters_snapshots_by_time() {
+        let recording = Recording { snapshots: vec![book(10, 0), book(20, 0), book(30, 0)] };
+        let start = NaiveDateTime::from_timestamp(10, 1);
+        let end = NaiveDateTime::from_timestamp(30, 0);
+
+        let between: Vec<_> = recording.snapshots_between(start, end).collect();
+        assert_eq!(between, vec![&recording.snapshots[1]]);
+    }
+}
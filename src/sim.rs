@@ -0,0 +1,281 @@
+//! An in-memory venue for testing strategies offline.
+//!
+//! `SimulatedVenue` keeps a book per stock and matches incoming orders against
+//! it with price-time priority, always filling at the resting order's price.
+//! It answers the read side of `StockfighterAPI` from that state. Fidelity is
+//! deliberately minimal: no accounts, positions or fees.
+
+use std::collections::{HashMap};
+use std::sync::{Mutex};
+
+use {Clock, Order, Orderbook, StockfighterAPI, StockfighterError, StockfighterResult, SystemClock, VenueInfo};
+
+
+#[derive(Copy, Clone, Debug)]
+pub enum SimOrderType {
+    /// Trade at `price` or better; any unfilled quantity rests on the book.
+    Limit(u64),
+    /// Trade against whatever is on the book; any unfilled quantity is dropped.
+    Market,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SimFill {
+    /// The id of the resting order this fill traded against.
+    pub resting_id: u64,
+    pub price: u64,
+    pub qty: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct SimOrderResult {
+    pub id: u64,
+    pub fills: Vec<SimFill>,
+    /// Quantity left resting on the book.
+    pub open_qty: u64,
+}
+
+
+#[derive(Copy, Clone, Debug)]
+struct Resting {
+    id: u64,
+    price: u64,
+    qty: u64,
+}
+
+#[derive(Debug, Default)]
+struct SimBook {
+    // Both sides are kept best-first, and in arrival order within a price.
+    bids: Vec<Resting>,
+    asks: Vec<Resting>,
+}
+
+#[derive(Debug, Default)]
+struct SimState {
+    next_id: u64,
+    books: HashMap<String, SimBook>,
+}
+
+
+#[derive(Debug)]
+pub struct SimulatedVenue<C: Clock = SystemClock> {
+    venue: String,
+    clock: C,
+    state: Mutex<SimState>,
+}
+
+impl SimulatedVenue<SystemClock> {
+    pub fn new<S: Into<String>>(venue: S) -> SimulatedVenue<SystemClock> {
+        SimulatedVenue::with_clock(venue, SystemClock)
+    }
+}
+
+impl<C: Clock> SimulatedVenue<C> {
+    /// A venue whose orderbook timestamps come from `clock`, e.g. a `MockClock`.
+    pub fn with_clock<S: Into<String>>(venue: S, clock: C) -> SimulatedVenue<C> {
+        SimulatedVenue {
+            venue: venue.into(),
+            clock,
+            state: Mutex::new(SimState::default()),
+        }
+    }
+
+    pub fn place_order(&self, stock: &str, is_buy: bool, qty: u64, order_type: SimOrderType) -> SimOrderResult {
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        let id = state.next_id;
+
+        let book = state.books.entry(stock.to_owned()).or_default();
+        let (opposite, same) = if is_buy { (&mut book.asks, &mut book.bids) } else { (&mut book.bids, &mut book.asks) };
+
+        let crosses = |resting: &Resting| match order_type {
+            SimOrderType::Market => true,
+            SimOrderType::Limit(price) => if is_buy { resting.price <= price } else { resting.price >= price },
+        };
+
+        let mut remaining = qty;
+        let mut fills = Vec::new();
+        while remaining > 0 && !opposite.is_empty() && crosses(&opposite[0]) {
+            let take = if opposite[0].qty < remaining { opposite[0].qty } else { remaining };
+            fills.push(SimFill { resting_id: opposite[0].id, price: opposite[0].price, qty: take });
+
+            remaining -= take;
+            opposite[0].qty -= take;
+            if opposite[0].qty == 0 {
+                opposite.remove(0);
+            }
+        }
+
+        let open_qty = match order_type {
+            SimOrderType::Limit(price) if remaining > 0 => {
+                // Behind every order at the same price or better.
+                let at = same.iter()
+                    .position(|resting| if is_buy { resting.price < price } else { resting.price > price })
+                    .unwrap_or(same.len());
+                same.insert(at, Resting { id, price, qty: remaining });
+                remaining
+            },
+            _ => 0,
+        };
+
+        SimOrderResult {
+            id,
+            fills,
+            open_qty,
+        }
+    }
+
+    /// Pulls a resting order, returning the quantity that was still open.
+    pub fn cancel_order(&self, stock: &str, id: u64) -> StockfighterResult<u64> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(book) = state.books.get_mut(stock) {
+            for side in [&mut book.bids, &mut book.asks] {
+                if let Some(i) = side.iter().position(|resting| resting.id == id) {
+                    return Ok(side.remove(i).qty);
+                }
+            }
+        }
+
+        Err(StockfighterError::Api(format!("No open order {} on {}", id, stock)))
+    }
+
+    fn check_venue(&self, venue: &str) -> StockfighterResult<()> {
+        if venue == self.venue {
+            Ok(())
+        } else {
+            Err(StockfighterError::Api(format!("No venue exists with the symbol {}", venue)))
+        }
+    }
+}
+
+impl<C: Clock> StockfighterAPI for SimulatedVenue<C> {
+    fn heartbeat(&self) -> StockfighterResult<()> {
+        Ok(())
+    }
+
+    fn venues(&self) -> StockfighterResult<Vec<VenueInfo>> {
+        Ok(vec![VenueInfo {
+            id: 1,
+            name: format!("{} (simulated)", self.venue),
+            is_open: true,
+            venue: self.venue.clone(),
+        }])
+    }
+
+    fn venue_heartbeat(&self, venue: &str) -> StockfighterResult<()> {
+        self.check_venue(venue)
+    }
+
+    fn stock_orderbook(&self, venue: &str, stock: &str) -> StockfighterResult<Orderbook> {
+        self.check_venue(venue)?;
+
+        let state = self.state.lock().unwrap();
+        let to_orders = |side: &[Resting], is_buy: bool| -> Vec<Order> {
+            side.iter().map(|resting| Order { price: resting.price, qty: resting.qty, is_buy }).collect()
+        };
+
+        let (bids, asks) = match state.books.get(stock) {
+            Some(book) => (to_orders(&book.bids, true), to_orders(&book.asks, false)),
+            None => (Vec::new(), Vec::new()),
+        };
+
+        Ok(Orderbook {
+            bids,
+            asks,
+            timestamp: self.clock.now(),
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Order, StockfighterAPI, StockfighterError};
+
+    fn fills(result: &SimOrderResult) -> Vec<(u64, u64, u64)> {
+        result.fills.iter().map(|fill| (fill.resting_id, fill.price, fill.qty)).collect()
+    }
+
+    // (price, qty) pairs, best first.
+    type Side = Vec<(u64, u64)>;
+
+    fn book(venue: &SimulatedVenue) -> (Side, Side) {
+        let book = venue.stock_orderbook("TESTEX", "FOOBAR").unwrap();
+        let side = |orders: &[Order]| orders.iter().map(|order| (order.price, order.qty)).collect();
+        (side(&book.bids), side(&book.asks))
+    }
+
+    #[test]
+    fn a_limit_order_sweeps_levels_at_the_resting_prices() {
+        let venue = SimulatedVenue::new("TESTEX");
+        let a = venue.place_order("FOOBAR", false, 5, SimOrderType::Limit(5200)).id;
+        let b = venue.place_order("FOOBAR", false, 5, SimOrderType::Limit(5210)).id;
+        venue.place_order("FOOBAR", false, 5, SimOrderType::Limit(5220));
+
+        let result = venue.place_order("FOOBAR", true, 12, SimOrderType::Limit(5215));
+        assert_eq!(fills(&result), vec![(a, 5200, 5), (b, 5210, 5)]);
+        assert_eq!(result.open_qty, 2);
+        assert_eq!(book(&venue), (vec![(5215, 2)], vec![(5220, 5)]));
+    }
+
+    #[test]
+    fn earlier_orders_at_a_price_fill_first() {
+        let venue = SimulatedVenue::new("TESTEX");
+        let first = venue.place_order("FOOBAR", false, 5, SimOrderType::Limit(5200)).id;
+        let second = venue.place_order("FOOBAR", false, 5, SimOrderType::Limit(5200)).id;
+
+        let result = venue.place_order("FOOBAR", true, 7, SimOrderType::Market);
+        assert_eq!(fills(&result), vec![(first, 5200, 5), (second, 5200, 2)]);
+        assert_eq!(book(&venue), (vec![], vec![(5200, 3)]));
+    }
+
+    #[test]
+    fn a_market_order_drops_what_the_book_cant_fill() {
+        let venue = SimulatedVenue::new("TESTEX");
+
+        let result = venue.place_order("FOOBAR", true, 10, SimOrderType::Market);
+        assert!(result.fills.is_empty());
+        assert_eq!(result.open_qty, 0);
+        assert_eq!(book(&venue), (vec![], vec![]));
+
+        let thin = venue.place_order("FOOBAR", false, 3, SimOrderType::Limit(5200)).id;
+        let result = venue.place_order("FOOBAR", true, 10, SimOrderType::Market);
+        assert_eq!(fills(&result), vec![(thin, 5200, 3)]);
+        assert_eq!(result.open_qty, 0);
+        assert_eq!(book(&venue), (vec![], vec![]));
+    }
+
+    #[test]
+    fn a_limit_remainder_rests_behind_orders_at_its_price() {
+        let venue = SimulatedVenue::new("TESTEX");
+        let ahead = venue.place_order("FOOBAR", true, 5, SimOrderType::Limit(5100)).id;
+        venue.place_order("FOOBAR", true, 5, SimOrderType::Limit(5050));
+        venue.place_order("FOOBAR", false, 2, SimOrderType::Limit(5150));
+
+        let behind = venue.place_order("FOOBAR", true, 6, SimOrderType::Limit(5150));
+        assert_eq!(behind.open_qty, 4);
+        venue.place_order("FOOBAR", true, 1, SimOrderType::Limit(5100));
+        assert_eq!(book(&venue), (vec![(5150, 4), (5100, 5), (5100, 1), (5050, 5)], vec![]));
+
+        let result = venue.place_order("FOOBAR", false, 6, SimOrderType::Limit(5100));
+        assert_eq!(fills(&result), vec![(behind.id, 5150, 4), (ahead, 5100, 2)]);
+    }
+
+    #[test]
+    fn cancelling_returns_the_open_quantity() {
+        let venue = SimulatedVenue::new("TESTEX");
+        let resting = venue.place_order("FOOBAR", false, 10, SimOrderType::Limit(5200)).id;
+        venue.place_order("FOOBAR", true, 4, SimOrderType::Market);
+
+        assert_eq!(venue.cancel_order("FOOBAR", resting).unwrap(), 6);
+        assert_eq!(book(&venue), (vec![], vec![]));
+
+        for &(stock, id) in &[("FOOBAR", resting), ("FOOBAR", 99), ("OTHER", resting)] {
+            match venue.cancel_order(stock, id) {
+                Err(StockfighterError::Api(ref message)) => assert_eq!(*message, format!("No open order {} on {}", id, stock)),
+                other => panic!("expected an Api error, got {:?}", other),
+            }
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use {Orderbook};
+
+
+/// Tracks an exponentially weighted mean and variance of the spread, to flag a
+/// market that is unusually wide compared to its recent history.
+#[derive(Clone, Debug)]
+pub struct SpreadMonitor {
+    alpha: f64,
+    k: f64,
+    warmup: u64,
+    samples: u64,
+    mean: Option<f64>,
+    variance: f64,
+}
+
+impl SpreadMonitor {
+    /// `alpha` is the weight of each new observation, in `(0, 1]`; `k` is how many
+    /// standard deviations above the mean count as abnormal. Nothing is flagged
+    /// until `warmup` spreads have been seen, since the variance of a handful of
+    /// observations says little (after one, it is zero).
+    pub fn new(alpha: f64, k: f64, warmup: u64) -> SpreadMonitor {
+        assert!(alpha > 0.0 && alpha <= 1.0, "alpha must be in (0, 1], got {}", alpha);
+
+        SpreadMonitor {
+            alpha,
+            k,
+            warmup,
+            samples: 0,
+            mean: None,
+            variance: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, spread: u64) {
+        self.samples += 1;
+        let spread = spread as f64;
+        match self.mean {
+            None => self.mean = Some(spread),
+            Some(mean) => {
+                let diff = spread - mean;
+                let step = self.alpha * diff;
+                self.mean = Some(mean + step);
+                self.variance = (1.0 - self.alpha) * (self.variance + diff * step);
+            },
+        }
+    }
+
+    /// Feeds the spread of `book`. One-sided books have no spread and are skipped.
+    pub fn ingest(&mut self, book: &Orderbook) {
+        if let Some(spread) = book.spread() {
+            self.update(spread);
+        }
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        self.mean
+    }
+
+    pub fn stddev(&self) -> Option<f64> {
+        self.mean.map(|_| self.variance.sqrt())
+    }
+
+    /// Whether enough spreads have been seen for `is_abnormal` to say anything.
+    pub fn is_warm(&self) -> bool {
+        self.samples > 0 && self.samples >= self.warmup
+    }
+
+    /// Whether `current` is wider than `mean + k * stddev`. Always false during
+    /// the warm-up.
+    pub fn is_abnormal(&self, current: u64) -> bool {
+        match self.mean {
+            Some(mean) if self.is_warm() => current as f64 > mean + self.k * self.variance.sqrt(),
+            _ => false,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Orderbook};
+
+    // Spreads wobbling between 9 and 11 ticks.
+    fn steady_market(monitor: &mut SpreadMonitor, n: usize) {
+        for spread in [10, 11, 9, 10, 10, 11, 9, 10].iter().cycle().take(n) {
+            monitor.update(*spread);
+        }
+    }
+
+    #[test]
+    fn converges_on_a_steady_spread() {
+        let mut monitor = SpreadMonitor::new(0.1, 3.0, 5);
+        steady_market(&mut monitor, 200);
+
+        let mean = monitor.mean().unwrap();
+        let stddev = monitor.stddev().unwrap();
+        assert!((mean - 10.0).abs() < 0.5, "mean {}", mean);
+        assert!(stddev > 0.3 && stddev < 1.5, "stddev {}", stddev);
+
+        assert!(!monitor.is_abnormal(11));
+        assert!(monitor.is_abnormal(25));
+    }
+
+    #[test]
+    fn flags_nothing_during_the_warm_up() {
+        let mut monitor = SpreadMonitor::new(0.2, 3.0, 4);
+        assert!(!monitor.is_abnormal(1000));
+
+        monitor.update(10);
+        // One observation means zero variance; without the warm-up, a single
+        // tick wider would already look abnormal.
+        assert!(!monitor.is_warm());
+        assert!(!monitor.is_abnormal(11));
+        assert!(!monitor.is_abnormal(1000));
+
+        steady_market(&mut monitor, 3);
+        assert!(monitor.is_warm());
+        assert!(monitor.is_abnormal(1000));
+    }
+
+    #[test]
+    fn skips_one_sided_books() {
+        let mut monitor = SpreadMonitor::new(0.5, 2.0, 1);
+        monitor.ingest(&Orderbook::builder().bid(5100, 10).build());
+        assert_eq!(monitor.mean(), None);
+
+        monitor.ingest(&Orderbook::builder().bid(5100, 10).ask(5110, 10).build());
+        assert_eq!(monitor.mean(), Some(10.0));
+    }
+}